@@ -0,0 +1,159 @@
+use std::num::NonZeroUsize;
+use clap::{Parser, Subcommand, ValueEnum};
+
+/// Search for PATTERN in each line of PATH.
+///
+/// PATH may be a file or a directory, which is searched recursively. With no
+/// PATH, or when it is `-`, standard input is searched. Exits with 0 when a
+/// line was selected, 1 when none were, and 2 on error.
+#[derive(Debug, Parser)]
+#[command(name = "minigrep", version, about, long_about)]
+pub(crate) struct Args {
+    /// Pattern to search for. When -e or -f is used, this is taken as PATH instead.
+    #[arg(value_name = "PATTERN")]
+    pub pattern: Option<String>,
+    /// File or directory to search.
+    #[arg(value_name = "PATH")]
+    pub path: Option<String>,
+    /// Search for PATTERN; may be given several times to match any of them.
+    #[arg(short = 'e', long = "regexp", value_name = "PATTERN")]
+    pub patterns: Vec<String>,
+    /// Read patterns from FILE, one per line.
+    #[arg(short = 'f', long = "file", value_name = "FILE")]
+    pub pattern_files: Vec<String>,
+    /// Treat patterns as regular expressions.
+    #[arg(long, overrides_with = "fixed_strings")]
+    pub regex: bool,
+    /// Treat patterns as literal strings (the default; overrides --regex).
+    #[arg(short = 'F', long, overrides_with = "regex")]
+    pub fixed_strings: bool,
+    /// Only match whole words.
+    #[arg(short, long)]
+    pub word_regexp: bool,
+    /// Only match whole lines.
+    #[arg(short = 'x', long)]
+    pub line_regexp: bool,
+    /// Match case-insensitively. Also enabled by the IGNORE_CASE environment variable.
+    #[arg(short, long, overrides_with = "case_sensitive")]
+    pub ignore_case: bool,
+    /// Match case-sensitively, even when IGNORE_CASE is set.
+    #[arg(short = 's', long, overrides_with = "ignore_case")]
+    pub case_sensitive: bool,
+    /// Only search files of type TYPE, such as rust, toml or html. May be
+    /// given several times.
+    #[arg(short = 't', long = "type", value_name = "TYPE")]
+    pub types: Vec<String>,
+    /// Don't search files of type TYPE. May be given several times.
+    #[arg(short = 'T', long = "type-not", value_name = "TYPE")]
+    pub types_not: Vec<String>,
+    /// Define a file type as NAME:GLOB, e.g. `web:*.{html,css}`, for use with
+    /// -t and -T. Adding to an existing type extends it.
+    #[arg(long, value_name = "NAME:GLOB")]
+    pub type_add: Vec<String>,
+    /// Skip files that can't match using the index from `minigrep index
+    /// build`. Files changed since it was built are still searched.
+    #[arg(long)]
+    pub index: bool,
+    /// Search hidden files and directories.
+    #[arg(long)]
+    pub hidden: bool,
+    /// Don't respect .gitignore and .ignore files.
+    #[arg(long)]
+    pub no_ignore: bool,
+    /// Prefix each line with its line number.
+    #[arg(short = 'n', long)]
+    pub line_number: bool,
+    /// Prefix each line with its byte offset.
+    #[arg(short, long)]
+    pub byte_offset: bool,
+    /// Print NUM lines of trailing context after each match.
+    #[arg(short = 'A', long, value_name = "NUM")]
+    pub after_context: Option<usize>,
+    /// Print NUM lines of leading context before each match.
+    #[arg(short = 'B', long, value_name = "NUM")]
+    pub before_context: Option<usize>,
+    /// Print NUM lines of context around each match.
+    #[arg(short = 'C', long, value_name = "NUM")]
+    pub context: Option<usize>,
+    /// Number of worker threads for directory searches [default: available cores].
+    #[arg(short = 'j', long, value_name = "N")]
+    pub threads: Option<NonZeroUsize>,
+    /// Print results as JSON Lines.
+    #[arg(long, conflicts_with = "mode")]
+    pub json: bool,
+    /// Select non-matching lines.
+    #[arg(short = 'v', long)]
+    pub invert_match: bool,
+    /// Stop reading a file after NUM selected lines.
+    #[arg(short, long, value_name = "NUM")]
+    pub max_count: Option<usize>,
+    /// Print only a count of selected lines per file.
+    #[arg(short, long, group = "mode")]
+    pub count: bool,
+    /// Print only the names of files with selected lines.
+    #[arg(short = 'l', long, group = "mode")]
+    pub files_with_matches: bool,
+    /// Print only the names of files without selected lines.
+    #[arg(short = 'L', long, group = "mode")]
+    pub files_without_match: bool,
+    /// Print nothing; exit with 0 as soon as any line is selected.
+    #[arg(short, long, group = "mode")]
+    pub quiet: bool,
+    /// Replace matches with TEMPLATE and show the changes as a diff. In regex
+    /// mode, $1 or ${name} insert capture groups.
+    #[arg(short, long, value_name = "TEMPLATE", conflicts_with_all = ["mode", "json", "invert_match"])]
+    pub replace: Option<String>,
+    /// Write replacements back to the files instead of showing a diff.
+    #[arg(long, requires = "replace", conflicts_with = "search_zip")]
+    pub in_place: bool,
+    /// Match lines containing the pattern within N edits (Levenshtein
+    /// distance), listing the closest matches first.
+    #[arg(long, value_name = "N", conflicts_with_all = [
+        "regex", "word_regexp", "line_regexp", "multiline", "invert_match", "replace",
+        "context", "before_context", "after_context",
+    ])]
+    pub fuzzy: Option<usize>,
+    /// Let patterns match across line boundaries. The regex runs over the
+    /// whole input, and each match is printed with the range of lines it spans.
+    #[arg(short = 'U', long, conflicts_with = "replace")]
+    pub multiline: bool,
+    /// Search inside gzip, bzip2, xz and zstd compressed files.
+    #[arg(short = 'z', long)]
+    pub search_zip: bool,
+    /// When to highlight matches, paths and line numbers. `auto` colors only
+    /// a terminal, and not when NO_COLOR is set.
+    #[arg(long, value_name = "WHEN", value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Manage the trigram index that `minigrep --index` uses to skip files.
+#[derive(Debug, Parser)]
+#[command(name = "minigrep-index", bin_name = "minigrep index", version)]
+pub(crate) struct IndexArgs {
+    #[command(subcommand)]
+    pub command: IndexCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum IndexCommand {
+    /// Index the files under DIR, or bring its index up to date by re-reading
+    /// only the files that changed.
+    Build {
+        /// Directory to index; the index is written to DIR/.minigrep-index.
+        #[arg(value_name = "DIR")]
+        dir: String,
+        /// Index hidden files and directories.
+        #[arg(long)]
+        hidden: bool,
+        /// Don't respect .gitignore and .ignore files.
+        #[arg(long)]
+        no_ignore: bool,
+    },
+}
@@ -0,0 +1,96 @@
+use std::io::{self, BufRead, BufReader};
+
+/// Compression formats recognized by their leading magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+/// What follows bzip2's `BZh` and block size digit: the magic starting the
+/// first block, or the one ending the stream when it has no blocks at all.
+const BZIP2_BLOCK: &[u8] = &[0x31, 0x41, 0x59, 0x26, 0x53, 0x59];
+const BZIP2_END: &[u8] = &[0x17, 0x72, 0x45, 0x38, 0x50, 0x90];
+
+/// Identifies the format of a stream from its first few bytes.
+pub fn detect(head: &[u8]) -> Option<Compression> {
+    if head.starts_with(&[0x1f, 0x8b]) {
+        Some(Compression::Gzip)
+    } else if is_bzip2(head) {
+        Some(Compression::Bzip2)
+    } else if head.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Some(Compression::Xz)
+    } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Some(Compression::Zstd)
+    } else {
+        None
+    }
+}
+
+/// `BZh` alone is too likely at the start of a text file to go by.
+fn is_bzip2(head: &[u8]) -> bool {
+    match head {
+        [b'B', b'Z', b'h', b'1'..=b'9', magic @ ..] => magic.starts_with(BZIP2_BLOCK) || magic.starts_with(BZIP2_END),
+        _ => false,
+    }
+}
+
+/// Wraps `reader` in a decoder when it starts with a known compression
+/// format; anything else is passed through unchanged. Concatenated streams,
+/// as left behind by log rotation appending to an archive, decode as one.
+pub fn decoder<'a, R: BufRead + 'a>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    let Some(format) = detect(reader.fill_buf()?) else {
+        return Ok(Box::new(reader));
+    };
+
+    Ok(match format {
+        Compression::Gzip => Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        Compression::Bzip2 => Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader))),
+        Compression::Xz => Box::new(BufReader::new(xz2::bufread::XzDecoder::new_multi_decoder(reader))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    const POEM: &str = "How public, like a frog\nTo tell your name the livelong day\n";
+
+    fn decode(compressed: Vec<u8>) -> String {
+        let mut decoded = String::new();
+        decoder(&compressed[..]).unwrap().read_to_string(&mut decoded).unwrap();
+        decoded
+    }
+
+    #[test]
+    fn decodes_every_format(){
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(POEM.as_bytes()).unwrap();
+        assert_eq!(POEM, decode(gz.finish().unwrap()));
+
+        let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bz.write_all(POEM.as_bytes()).unwrap();
+        assert_eq!(POEM, decode(bz.finish().unwrap()));
+        let empty = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        assert_eq!("", decode(empty.finish().unwrap()));
+
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(POEM.as_bytes()).unwrap();
+        assert_eq!(POEM, decode(xz.finish().unwrap()));
+
+        assert_eq!(POEM, decode(zstd::encode_all(POEM.as_bytes(), 0).unwrap()));
+    }
+
+    #[test]
+    fn passes_plain_text_through(){
+        assert_eq!(None, detect(POEM.as_bytes()));
+        assert_eq!(POEM, decode(POEM.as_bytes().to_vec()));
+        let text = "BZh is a prefix frog\n";
+        assert_eq!(None, detect(text.as_bytes()));
+        assert_eq!(text, decode(text.as_bytes().to_vec()));
+    }
+}
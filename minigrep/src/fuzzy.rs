@@ -0,0 +1,164 @@
+use std::io::{self, BufRead};
+use std::ops::Range;
+use crate::{fold_case, fold_case_indexed, search_reader, unfold_span, Match, Query, SearchOptions, Sink, Stats};
+
+/// An approximate query: a line matches when some stretch of it is within
+/// `max_distance` edits (insertions, deletions or substitutions of a single
+/// character) of the pattern.
+#[derive(Debug, Clone)]
+pub struct Fuzzy {
+    /// Case-folded already when `ignore_case` is set.
+    pattern: Vec<char>,
+    max_distance: usize,
+    ignore_case: bool,
+}
+
+impl Fuzzy {
+    pub fn new(pattern: &str, max_distance: usize, ignore_case: bool) -> Fuzzy {
+        let pattern = if ignore_case { fold_case(pattern) } else { pattern.to_string() };
+        Fuzzy { pattern: pattern.chars().collect(), max_distance, ignore_case }
+    }
+
+    /// The stretch of `line` closest to the pattern, as its edit distance and
+    /// byte range, if it is close enough. Ties go to the one ending first.
+    pub fn best_match(&self, line: &str) -> Option<(usize, Range<usize>)> {
+        if !self.ignore_case {
+            let (distance, chars) = self.closest(line)?;
+            let starts: Vec<usize> = line.char_indices().map(|(i, _)| i).chain([line.len()]).collect();
+            return Some((distance, starts[chars.start]..starts[chars.end]));
+        }
+
+        let (folded, origins) = fold_case_indexed(line);
+        let (distance, chars) = self.closest(&folded)?;
+        let starts: Vec<usize> = folded.char_indices().map(|(i, _)| i).chain([folded.len()]).collect();
+        Some((distance, unfold_span(line, &origins, starts[chars.start]..starts[chars.end])))
+    }
+
+    /// Sellers' variant of the Levenshtein table, where a match may begin
+    /// anywhere in `text` for free. Returns the smallest distance found and
+    /// the range of chars it covers.
+    fn closest(&self, text: &str) -> Option<(usize, Range<usize>)> {
+        let m = self.pattern.len();
+        // distance[i]: cost of matching the first i pattern chars so that the
+        // alignment ends at the current text position; start[i]: where it began
+        let mut distance: Vec<usize> = (0..=m).collect();
+        let mut start = vec![0; m + 1];
+        let mut best = (distance[m] <= self.max_distance).then_some((distance[m], 0..0));
+
+        for (j, c) in text.chars().enumerate() {
+            let (mut diagonal, mut diagonal_start) = (distance[0], start[0]);
+            distance[0] = 0;
+            start[0] = j + 1;
+            for i in 1..=m {
+                let substitute = diagonal + usize::from(self.pattern[i - 1] != c);
+                let (above, above_start) = (distance[i], start[i]);
+                // on a tie, the alignment starting earlier covers the whole typo
+                let (cost, from) = [
+                    (substitute, diagonal_start),
+                    (above + 1, above_start),
+                    (distance[i - 1] + 1, start[i - 1]),
+                ].into_iter().min_by_key(|&(cost, from)| (cost, from)).unwrap();
+                (diagonal, diagonal_start) = (above, above_start);
+                distance[i] = cost;
+                start[i] = from;
+            }
+            if distance[m] <= self.max_distance && best.as_ref().is_none_or(|(d, _)| distance[m] < *d) {
+                best = Some((distance[m], start[m]..j + 1));
+            }
+        }
+
+        best
+    }
+}
+
+/// Runs a fuzzy `query` over `reader` like `search_reader`, but hands the
+/// selected lines to `sink` best first: by edit distance, then in input
+/// order. Ranking needs every match of the input in hand, so they are all
+/// held in memory, and `max_count` keeps the best ones rather than the
+/// first ones. Each line is scanned once, which yields both whether it is
+/// selected and how close it comes.
+pub fn search_ranked<R: BufRead, S: Sink>(query: &Query, options: SearchOptions, mut reader: R, sink: &mut S) -> io::Result<Stats> {
+    let Query::Fuzzy(fuzzy) = query else {
+        return search_reader(query, options, reader, sink);
+    };
+
+    let mut matches = Vec::new();
+    let mut buf = Vec::new();
+    let mut offset = 0;
+    let mut line_index = 0;
+    loop {
+        buf.clear();
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 {
+            break;
+        }
+        let line = buf.strip_suffix(b"\n").map_or(&buf[..], |l| l.strip_suffix(b"\r").unwrap_or(l));
+        let text = String::from_utf8_lossy(line);
+        if let Some((distance, span)) = fuzzy.best_match(&text) {
+            let byte_range = offset..offset + line.len();
+            matches.push(RankedMatch { distance, line_index, byte_range, text: text.into_owned(), span });
+        }
+        offset += read;
+        line_index += 1;
+    }
+
+    // the sort is stable, so equally close lines stay in input order
+    matches.sort_by_key(|m| m.distance);
+    matches.truncate(options.max_count.unwrap_or(usize::MAX));
+
+    for m in &matches {
+        let spans = vec![m.span.clone()];
+        sink.matched(&Match { line_index: m.line_index, byte_range: m.byte_range.clone(), text: &m.text, spans, groups: Vec::new() })?;
+    }
+    Ok(Stats {
+        searches: 1,
+        searches_with_match: usize::from(!matches.is_empty()),
+        searched_lines: line_index,
+        matched_lines: matches.len(),
+        bytes_searched: offset,
+    })
+}
+
+/// An owned copy of a selected line, kept until ranking is done.
+struct RankedMatch {
+    distance: usize,
+    line_index: usize,
+    byte_range: Range<usize>,
+    text: String,
+    span: Range<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closest_stretch(){
+        let fuzzy = Fuzzy::new("somebody", 2, false);
+        assert_eq!(Some((0, 17..25)), fuzzy.best_match("How dreary to be somebody!"));
+        assert_eq!(Some((1, 17..24)), fuzzy.best_match("How dreary to be smebody!"));
+        assert_eq!(None, fuzzy.best_match("How public, like a frog"));
+
+        let fuzzy = Fuzzy::new("FROG", 1, true);
+        assert_eq!(Some((1, 19..24)), fuzzy.best_match("How public, like a frrog"));
+    }
+
+    struct Texts(Vec<String>);
+
+    impl Sink for Texts {
+        fn matched(&mut self, m: &Match) -> io::Result<()> {
+            self.0.push(m.text.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn ranks_by_distance(){
+        let query = Query::Fuzzy(Fuzzy::new("frog", 1, false));
+        let input: &[u8] = b"a fog\na frog\na bog\n";
+        let mut texts = Texts(Vec::new());
+        let stats = search_ranked(&query, SearchOptions::default(), input, &mut texts).unwrap();
+        assert_eq!(vec!["a frog", "a fog"], texts.0);
+        assert_eq!((3, 2), (stats.searched_lines, stats.matched_lines));
+    }
+}
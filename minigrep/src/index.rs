@@ -0,0 +1,470 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::UNIX_EPOCH;
+use clap::Parser;
+use crate::args::{IndexArgs, IndexCommand};
+use crate::walk::{self, WalkOptions};
+use crate::{fold_case, Config, OutputMode};
+
+/// Name of the index file, kept at the root of the indexed directory.
+pub const INDEX_FILE: &str = ".minigrep-index";
+/// What an index file starts with, followed by its version.
+const MAGIC: &[u8] = b"minigrep";
+/// Bumped whenever the layout of the index file changes.
+const VERSION: u32 = 2;
+/// Bytes taken by a trigram and the end of its postings.
+const DIRECTORY_ENTRY: usize = 8;
+
+/// `minigrep index build DIR`, parsed.
+#[derive(Debug)]
+pub struct IndexConfig {
+    pub root: PathBuf,
+    pub walk: WalkOptions,
+}
+
+impl IndexConfig {
+    /// Whether `args`, program name included, ask for `minigrep index` rather
+    /// than a search. To search a file called `build` for the word `index`,
+    /// use `-e index`.
+    pub fn requested(args: &[OsString]) -> bool {
+        args.get(1).is_some_and(|arg| arg == "index")
+            && matches!(args.get(2).and_then(|arg| arg.to_str()), Some("build" | "help" | "-h" | "--help"))
+    }
+
+    pub fn build(args: &[OsString]) -> Result<IndexConfig, clap::Error> {
+        // `index` takes the place of the program name
+        let args = IndexArgs::try_parse_from(&args[1..])?;
+        let IndexCommand::Build { dir, hidden, no_ignore } = args.command;
+        Ok(IndexConfig { root: PathBuf::from(dir), walk: WalkOptions { hidden, no_ignore, types: None } })
+    }
+
+    pub fn run(config: IndexConfig) -> Result<(), Box<dyn Error>> {
+        let stats = build(&config.root, config.walk)?;
+        println!(
+            "{}: indexed {} files, {} unchanged, {} removed",
+            config.root.join(INDEX_FILE).display(), stats.indexed, stats.unchanged, stats.removed,
+        );
+        Ok(())
+    }
+}
+
+/// A file's modification time, as seconds and nanoseconds since the epoch,
+/// and its length; a file whose stamp changed is indexed again.
+type Stamp = ((u64, u32), u64);
+
+/// The index of the files under a directory: for every trigram, the ids of
+/// the files containing it. The file is read into memory as it is; loading
+/// only parses the list of files, and the posting list of a trigram is only
+/// decoded once a search asks for it.
+///
+/// Layout, little-endian throughout:
+///
+/// ```text
+/// "minigrep" version:u32
+/// files:u32      { path_len:u32 path secs:u64 nanos:u32 len:u64 }*  ids in this order
+/// trigrams:u32   { trigram:u32 end:u32 }*  ascending, `end` is where its postings stop
+/// postings       file ids as LEB128 deltas from the previous id of the same list
+/// ```
+#[derive(Debug)]
+pub struct Index {
+    /// Ids of the files, keyed by path relative to the root.
+    ids: HashMap<String, u32>,
+    /// Stamps of the files, by id.
+    stamps: Vec<Stamp>,
+    data: Vec<u8>,
+    directory: Range<usize>,
+    postings: Range<usize>,
+}
+
+impl Index {
+    pub fn load(root: &Path) -> io::Result<Index> {
+        let data = fs::read(root.join(INDEX_FILE))?;
+        let header = data.get(..MAGIC.len() + 4);
+        if header.is_none_or(|header| header[..MAGIC.len()] != *MAGIC || header[MAGIC.len()..] != VERSION.to_le_bytes()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "index is from another version of minigrep"));
+        }
+        Index::parse(data).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "index is corrupt"))
+    }
+
+    fn parse(data: Vec<u8>) -> Option<Index> {
+        let mut input = Input { data: &data, at: MAGIC.len() + 4 };
+        let n_files = input.u32()?;
+        let mut ids = HashMap::new();
+        let mut stamps = Vec::new();
+        for id in 0..n_files {
+            let len = input.u32()? as usize;
+            let path = std::str::from_utf8(input.bytes(len)?).ok()?.to_string();
+            stamps.push(((input.u64()?, input.u32()?), input.u64()?));
+            ids.insert(path, id);
+        }
+        let n_trigrams = input.u32()? as usize;
+        let start = input.at;
+        input.bytes(n_trigrams.checked_mul(DIRECTORY_ENTRY)?)?;
+        let directory = start..input.at;
+        let postings = input.at..data.len();
+        let index = Index { ids, stamps, data, directory, postings };
+        // the last posting list ends where the file does
+        let end = n_trigrams.checked_sub(1).map_or(0, |last| index.directory_entry(last).1);
+        (end == index.postings.len()).then_some(index)
+    }
+
+    /// Writes an index of `files`, keyed by path relative to `root`, beside
+    /// them, through a temporary file so a reader never sees half of it.
+    fn save(root: &Path, files: &BTreeMap<String, (Stamp, Vec<u32>)>) -> io::Result<()> {
+        let mut posting_lists: HashMap<u32, Vec<u32>> = HashMap::new();
+        for (id, (_, trigrams)) in (0..).zip(files.values()) {
+            for &trigram in trigrams {
+                posting_lists.entry(trigram).or_default().push(id);
+            }
+        }
+        let mut trigrams: Vec<u32> = posting_lists.keys().copied().collect();
+        trigrams.sort_unstable();
+
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&len_u32(files.len())?.to_le_bytes());
+        for (path, (((secs, nanos), len), _)) in files {
+            out.extend_from_slice(&len_u32(path.len())?.to_le_bytes());
+            out.extend_from_slice(path.as_bytes());
+            out.extend_from_slice(&secs.to_le_bytes());
+            out.extend_from_slice(&nanos.to_le_bytes());
+            out.extend_from_slice(&len.to_le_bytes());
+        }
+        let mut postings = Vec::new();
+        out.extend_from_slice(&len_u32(trigrams.len())?.to_le_bytes());
+        for trigram in &trigrams {
+            let mut previous = 0;
+            for &id in &posting_lists[trigram] {
+                write_varint(&mut postings, id - previous);
+                previous = id;
+            }
+            out.extend_from_slice(&trigram.to_le_bytes());
+            out.extend_from_slice(&len_u32(postings.len())?.to_le_bytes());
+        }
+        out.extend_from_slice(&postings);
+
+        let path = root.join(INDEX_FILE);
+        let temp = root.join(format!("{INDEX_FILE}.{}.tmp", process::id()));
+        let result = (|| {
+            let mut file = OpenOptions::new().write(true).create_new(true).open(&temp)?;
+            file.write_all(&out)?;
+            fs::rename(&temp, &path)
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result
+    }
+
+    /// Every indexed file with its stamp and trigrams, for updating the
+    /// index; unlike a search, this decodes every posting list.
+    fn into_files(self) -> Option<HashMap<String, (Stamp, Vec<u32>)>> {
+        let mut trigrams = vec![Vec::new(); self.stamps.len()];
+        for entry in 0..self.directory.len() / DIRECTORY_ENTRY {
+            let trigram = self.directory_entry(entry).0;
+            for id in self.posting_list(entry)? {
+                trigrams.get_mut(id as usize)?.push(trigram);
+            }
+        }
+        let mut files = HashMap::new();
+        for (path, id) in self.ids {
+            let id = id as usize;
+            files.insert(path, (self.stamps[id], std::mem::take(&mut trigrams[id])));
+        }
+        Some(files)
+    }
+
+    /// The files that could match `query`: those holding every trigram of
+    /// at least one of its patterns.
+    pub fn candidates(&self, query: &TrigramQuery) -> Candidates<'_> {
+        let mut ids = vec![false; self.stamps.len()];
+        for trigrams in &query.any_of {
+            for id in self.files_with_all(trigrams) {
+                if let Some(candidate) = ids.get_mut(id as usize) {
+                    *candidate = true;
+                }
+            }
+        }
+        Candidates { index: self, ids }
+    }
+
+    /// Ids of the files that contain every one of `trigrams`, ascending.
+    fn files_with_all(&self, trigrams: &[u32]) -> Vec<u32> {
+        let mut lists = Vec::with_capacity(trigrams.len());
+        for &trigram in trigrams {
+            match self.find(trigram).and_then(|entry| self.posting_list(entry)) {
+                Some(list) => lists.push(list),
+                None => return Vec::new(),
+            }
+        }
+        // intersecting from the shortest list keeps the work small
+        lists.sort_by_key(Vec::len);
+        let mut lists = lists.into_iter();
+        let mut ids = lists.next().unwrap_or_default();
+        for list in lists {
+            ids.retain(|id| list.binary_search(id).is_ok());
+        }
+        ids
+    }
+
+    /// Binary searches the directory for `trigram`, returning its entry.
+    fn find(&self, trigram: u32) -> Option<usize> {
+        let (mut low, mut high) = (0, self.directory.len() / DIRECTORY_ENTRY);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.directory_entry(mid).0.cmp(&trigram) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    /// The trigram of directory entry `entry` and where its postings end.
+    fn directory_entry(&self, entry: usize) -> (u32, usize) {
+        let at = self.directory.start + entry * DIRECTORY_ENTRY;
+        let field = |at: usize| u32::from_le_bytes(self.data[at..at + 4].try_into().unwrap());
+        (field(at), field(at + 4) as usize)
+    }
+
+    fn posting_list(&self, entry: usize) -> Option<Vec<u32>> {
+        let start = if entry == 0 { 0 } else { self.directory_entry(entry - 1).1 };
+        let end = self.directory_entry(entry).1;
+        let mut input = Input { data: self.data.get(self.postings.start + start..self.postings.start + end)?, at: 0 };
+        let mut ids = Vec::new();
+        let mut id = 0u32;
+        while input.at < input.data.len() {
+            id = id.checked_add(input.varint()?)?;
+            ids.push(id);
+        }
+        Some(ids)
+    }
+}
+
+/// The files of an index that could match a query.
+#[derive(Debug)]
+pub struct Candidates<'a> {
+    index: &'a Index,
+    /// By file id.
+    ids: Vec<bool>,
+}
+
+impl Candidates<'_> {
+    /// Whether the file at `path`, somewhere under `root`, could match.
+    /// Files that weren't indexed, or changed since, might.
+    pub fn may_match(&self, root: &Path, path: &Path) -> bool {
+        let Some(&id) = self.index.ids.get(&key(root, path)) else {
+            return true;
+        };
+        let fresh = fs::metadata(path).and_then(|m| stamp(&m)).is_ok_and(|s| s == self.index.stamps[id as usize]);
+        !fresh || self.ids[id as usize]
+    }
+}
+
+/// Reads the fields of an index file, `None` once it runs out.
+struct Input<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Input<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.at..self.at.checked_add(len)?)?;
+        self.at += len;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn varint(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for shift in (0..32).step_by(7) {
+            let byte = self.bytes(1)?[0];
+            value |= u32::from(byte & 0x7f).checked_shl(shift)?;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn len_u32(len: usize) -> io::Result<u32> {
+    u32::try_from(len).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too much to index"))
+}
+
+/// The trigrams a file has to contain to possibly match a search: all of
+/// those of at least one of its patterns.
+#[derive(Debug)]
+pub struct TrigramQuery {
+    any_of: Vec<Vec<u32>>,
+}
+
+impl TrigramQuery {
+    /// `None` when the index can't narrow down the search in `config`: a
+    /// regex or fuzzy pattern may match text sharing no trigram with it, a
+    /// pattern under three bytes has none, some output modes need every file
+    /// however it matches, and compressed files aren't indexed decompressed.
+    pub fn new(config: &Config) -> Option<TrigramQuery> {
+        let needs_every_file = config.invert_match || matches!(config.mode, OutputMode::Count | OutputMode::FilesWithoutMatch);
+        if config.regex || config.fuzzy.is_some() || config.search_zip || needs_every_file || config.patterns.is_empty() {
+            return None;
+        }
+
+        let any_of = config.patterns.iter().map(|pattern| {
+            // the index holds both spellings, see `file_trigrams`
+            let pattern = if config.ignore_case { fold_case(pattern) } else { pattern.to_ascii_lowercase() };
+            let trigrams = trigrams(pattern.as_bytes());
+            (!trigrams.is_empty()).then_some(trigrams)
+        }).collect::<Option<_>>()?;
+        Some(TrigramQuery { any_of })
+    }
+}
+
+/// Totals reported by `minigrep index build`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BuildStats {
+    /// Files read because they were new or had changed.
+    pub indexed: usize,
+    /// Files kept from the previous index as they were.
+    pub unchanged: usize,
+    /// Files that were indexed before and are gone now.
+    pub removed: usize,
+}
+
+/// Indexes the files under `root`, or brings an existing index up to date by
+/// re-reading only files whose modification time or size changed. Files that
+/// can't be read are left out, so searches still open them and report why.
+pub fn build(root: &Path, options: WalkOptions) -> Result<BuildStats, Box<dyn Error>> {
+    // an unreadable or outdated index is rebuilt from scratch
+    let mut previous = Index::load(root).ok().and_then(Index::into_files).unwrap_or_default();
+    let mut files = BTreeMap::new();
+    let mut stats = BuildStats::default();
+
+    for path in walk::files(root, options)? {
+        if is_index_file(root, &path) {
+            continue;
+        }
+        // stat before reading: a write in between then shows up as a changed mtime later
+        let Ok(stamp) = fs::metadata(&path).and_then(|m| stamp(&m)) else {
+            continue;
+        };
+        let key = key(root, &path);
+        let entry = match previous.remove(&key) {
+            Some(entry) if entry.0 == stamp => {
+                stats.unchanged += 1;
+                entry
+            }
+            _ => {
+                let Ok(contents) = fs::read(&path) else {
+                    continue;
+                };
+                stats.indexed += 1;
+                let trigrams = if walk::is_binary(&contents) { Vec::new() } else { file_trigrams(&contents) };
+                (stamp, trigrams)
+            }
+        };
+        files.insert(key, entry);
+    }
+
+    stats.removed = previous.len();
+    Index::save(root, &files)?;
+    Ok(stats)
+}
+
+/// Whether `path` is the index of `root` itself, which is never searched.
+pub fn is_index_file(root: &Path, path: &Path) -> bool {
+    path == root.join(INDEX_FILE)
+}
+
+fn key(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root).unwrap_or(path).to_string_lossy().into_owned()
+}
+
+fn stamp(metadata: &fs::Metadata) -> io::Result<Stamp> {
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map_err(io::Error::other)?;
+    Ok(((modified.as_secs(), modified.subsec_nanos()), metadata.len()))
+}
+
+/// Trigrams of the ASCII-lowercased contents, which case-sensitive patterns
+/// are looked up in, together with those of the case-folded contents for
+/// case-insensitive ones. Either way a file only has to hold a superset.
+fn file_trigrams(contents: &[u8]) -> Vec<u32> {
+    let mut all = trigrams(&contents.to_ascii_lowercase());
+    all.extend(trigrams(fold_case(&String::from_utf8_lossy(contents)).as_bytes()));
+    all.sort_unstable();
+    all.dedup();
+    all
+}
+
+fn trigrams(bytes: &[u8]) -> Vec<u32> {
+    let mut trigrams: Vec<u32> = bytes.windows(3)
+        .map(|w| u32::from(w[0]) << 16 | u32::from(w[1]) << 8 | u32::from(w[2]))
+        .collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn shortlists_and_updates(){
+        let root = env::temp_dir().join(format!("minigrep-index-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("frog.txt"), "How public, like a Frog").unwrap();
+        fs::write(root.join("bog.txt"), "To an admiring bog!").unwrap();
+
+        let stats = build(&root, WalkOptions::default()).unwrap();
+        assert_eq!(BuildStats { indexed: 2, unchanged: 0, removed: 0 }, stats);
+
+        let index = Index::load(&root).unwrap();
+        let config = Config::build(["minigrep", "-s", "frog"]).unwrap();
+        let query = TrigramQuery::new(&config).unwrap();
+        let candidates = index.candidates(&query);
+        // only a superset: the case-sensitive search itself rules frog.txt out
+        assert!(candidates.may_match(&root, &root.join("frog.txt")));
+        assert!(!candidates.may_match(&root, &root.join("bog.txt")));
+        assert!(TrigramQuery::new(&Config::build(["minigrep", "--regex", "frog"]).unwrap()).is_none());
+
+        fs::write(root.join("bog.txt"), "a frog in the bog").unwrap();
+        fs::remove_file(root.join("frog.txt")).unwrap();
+        // a changed file is searched even before the index catches up
+        assert!(candidates.may_match(&root, &root.join("bog.txt")));
+        let stats = build(&root, WalkOptions::default()).unwrap();
+        assert_eq!(BuildStats { indexed: 1, unchanged: 0, removed: 1 }, stats);
+
+        let mut data = fs::read(root.join(INDEX_FILE)).unwrap();
+        data.truncate(data.len() - 1);
+        fs::write(root.join(INDEX_FILE), &data).unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, Index::load(&root).unwrap_err().kind());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
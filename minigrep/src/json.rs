@@ -0,0 +1,140 @@
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+use serde_json::{json, Value};
+use crate::{Match, Sink, Stats};
+
+/// Name reported for input read from stdin.
+const STDIN_PATH: &str = "<stdin>";
+
+/// Writes results as JSON Lines: one object per event, each tagged with a
+/// `type` of `begin`, `match` or `end` for a single input. A run finishes with
+/// a `summary` event written by `summary`. Submatches of a regex with
+/// capture groups list them under `groups`.
+pub struct JsonPrinter<W: Write> {
+    out: W,
+    path: String,
+}
+
+impl<W: Write> JsonPrinter<W> {
+    pub fn new(out: W) -> JsonPrinter<W> {
+        JsonPrinter { out, path: STDIN_PATH.to_string() }
+    }
+
+    /// Starts a new input; `None` means stdin.
+    pub fn begin(&mut self, path: Option<&Path>) -> io::Result<()> {
+        self.path = path.map_or_else(|| STDIN_PATH.to_string(), |p| p.display().to_string());
+        let path = self.path.clone();
+        self.event(json!({ "type": "begin", "path": path }))
+    }
+
+    pub fn end(&mut self, stats: &Stats) -> io::Result<()> {
+        let path = self.path.clone();
+        self.event(json!({ "type": "end", "path": path, "stats": stats_json(stats) }))
+    }
+
+    fn event(&mut self, event: Value) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, &event)?;
+        writeln!(self.out)
+    }
+}
+
+impl<W: Write> Sink for JsonPrinter<W> {
+    fn matched(&mut self, m: &Match) -> io::Result<()> {
+        let describe = |span: &Range<usize>| json!({
+            "match": &m.text[span.clone()],
+            "start": span.start,
+            "end": span.end,
+        });
+        let submatches: Vec<Value> = m.spans.iter().enumerate().map(|(i, span)| {
+            let mut submatch = describe(span);
+            // capture groups only come with regexes that have them
+            if let Some(groups) = m.groups.get(i) {
+                submatch["groups"] = groups.iter().map(|group| group.as_ref().map_or(Value::Null, describe)).collect();
+            }
+            submatch
+        }).collect();
+
+        let event = json!({
+            "type": "match",
+            "path": self.path,
+            "line_number": m.line_index + 1,
+            // past line_number only when a multiline match spans several lines
+            "end_line_number": m.last_line_index() + 1,
+            // 1-based byte column of the first submatch, like grep's --column
+            "column": m.spans.first().map_or(1, |span| span.start + 1),
+            "absolute_offset": m.byte_range.start,
+            "text": m.text,
+            "submatches": submatches,
+        });
+        self.event(event)
+    }
+}
+
+/// Writes the closing `summary` event with totals for the whole run.
+pub fn summary<W: Write>(mut out: W, stats: &Stats, elapsed: Duration) -> io::Result<()> {
+    let event = json!({
+        "type": "summary",
+        "stats": stats_json(stats),
+        "elapsed_secs": elapsed.as_secs_f64(),
+    });
+    serde_json::to_writer(&mut out, &event)?;
+    writeln!(out)
+}
+
+fn stats_json(stats: &Stats) -> Value {
+    json!({
+        "searches": stats.searches,
+        "searches_with_match": stats.searches_with_match,
+        "searched_lines": stats.searched_lines,
+        "matched_lines": stats.matched_lines,
+        "bytes_searched": stats.bytes_searched,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{search_reader, Query, SearchOptions};
+
+    #[test]
+    fn match_events(){
+        let query = Query::Literal("frog".to_string());
+        let mut printer = JsonPrinter::new(Vec::new());
+        printer.begin(Some(Path::new("poem.txt"))).unwrap();
+        let stats = search_reader(&query, SearchOptions::default(), &b"How public, like a frog\n"[..], &mut printer).unwrap();
+        printer.end(&stats).unwrap();
+
+        let events: Vec<Value> = String::from_utf8(printer.out).unwrap()
+            .lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(3, events.len());
+        assert_eq!(json!({ "type": "begin", "path": "poem.txt" }), events[0]);
+        assert_eq!(json!({
+            "type": "match",
+            "path": "poem.txt",
+            "line_number": 1,
+            "end_line_number": 1,
+            "column": 20,
+            "absolute_offset": 0,
+            "text": "How public, like a frog",
+            "submatches": [{ "match": "frog", "start": 19, "end": 23 }],
+        }), events[1]);
+        assert_eq!(1, events[2]["stats"]["matched_lines"]);
+    }
+
+    #[test]
+    fn capture_groups(){
+        let query = Query::Regex(regex::Regex::new(r"(\w+) (x)?frog").unwrap());
+        let mut printer = JsonPrinter::new(Vec::new());
+        search_reader(&query, SearchOptions::default(), &b"like a frog\n"[..], &mut printer).unwrap();
+
+        let event: Value = serde_json::from_slice(&printer.out).unwrap();
+        assert_eq!(json!([{
+            "match": "a frog",
+            "start": 5,
+            "end": 11,
+            "groups": [{ "match": "a", "start": 5, "end": 6 }, null],
+        }]), event["submatches"]);
+    }
+}
@@ -1,30 +1,894 @@
+use std::borrow::Cow;
+use std::env;
 use std::error::Error;
-use std::fs;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Instant;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use fuzzy::Fuzzy;
+use index::{Index, TrigramQuery, INDEX_FILE};
+use regex::{NoExpand, Regex, RegexBuilder};
+use args::{Args, ColorChoice};
+use json::JsonPrinter;
+use literals::Literals;
+use output::{paint, PrintOptions, Printer, PATH, SEPARATOR};
+use replace::{Preview, Template};
+use ignore::types::Types;
+use walk::WalkOptions;
+
+mod args;
+pub mod decompress;
+pub mod fuzzy;
+pub mod index;
+pub mod json;
+pub mod literals;
+pub mod output;
+pub mod parallel;
+pub mod replace;
+pub mod walk;
 
 pub struct Config {
-    pub q: String,
+    /// A line is selected when it matches any of these.
+    pub patterns: Vec<String>,
+    /// File or directory to search; `-` reads standard input.
     pub fp: String,
+    /// Treat `patterns` as regular expressions instead of plain substrings.
+    pub regex: bool,
+    /// Only match whole words.
+    pub word_regexp: bool,
+    /// Only match whole lines.
+    pub line_regexp: bool,
+    /// Match without regard to case. Set by `-i`, or by the `IGNORE_CASE`
+    /// environment variable when neither `-i` nor `-s` is given.
+    pub ignore_case: bool,
+    /// Restricts a directory search to files of the selected types.
+    pub types: Option<Types>,
+    /// Shortlist the files of a directory search with its trigram index.
+    pub index: bool,
+    /// Search hidden files and directories when `fp` is a directory.
+    pub hidden: bool,
+    /// Search paths excluded by `.gitignore` and `.ignore` files.
+    pub no_ignore: bool,
+    /// Prefix each printed line with its 1-based line number.
+    pub line_number: bool,
+    /// Prefix each printed line with the byte offset of its start.
+    pub byte_offset: bool,
+    /// Lines of leading context to print before each match.
+    pub before_context: usize,
+    /// Lines of trailing context to print after each match.
+    pub after_context: usize,
+    /// Worker threads used when searching a directory.
+    pub threads: usize,
+    /// Emit results as JSON Lines events instead of grep-style text.
+    pub json: bool,
+    /// Select the lines that don't match instead of those that do.
+    pub invert_match: bool,
+    /// Stop reading an input after this many selected lines.
+    pub max_count: Option<usize>,
+    /// What to report for each input.
+    pub mode: OutputMode,
+    /// Replace each match with this template; `$1`, `${name}` and so on
+    /// refer to capture groups in regex mode.
+    pub replace: Option<String>,
+    /// Write replacements back to the files instead of previewing them.
+    pub in_place: bool,
+    /// Highlight matches, paths and line numbers with ANSI colors.
+    pub color: bool,
+    /// Match lines within this many edits of the pattern, closest first.
+    pub fuzzy: Option<usize>,
+    /// Run the query over whole inputs so matches can span several lines.
+    pub multiline: bool,
+    /// Search the decompressed contents of gzip, bzip2, xz and zstd inputs.
+    pub search_zip: bool,
+}
+
+/// What a search reports for each input.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    /// The selected lines themselves.
+    #[default]
+    Lines,
+    /// Only how many lines were selected.
+    Count,
+    /// Only the names of inputs with a selected line.
+    FilesWithMatches,
+    /// Only the names of inputs without any selected line.
+    FilesWithoutMatch,
+    /// Nothing; the exit status says whether anything was selected.
+    Quiet,
 }
 
 impl Config {
-    pub fn build(args:&[String]) -> Result<Config, &'static str> {
-        if args.len() < 3 {
-            return Err("not enough arguments");
+    /// Parses command-line arguments, including the program name. Errors
+    /// carry their own usage text; `--help` and `--version` come back as
+    /// errors too, and `clap::Error::exit` prints and exits appropriately.
+    pub fn build<I, T>(args: I) -> Result<Config, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let args = Args::try_parse_from(args)?;
+
+        let mut patterns = args.patterns;
+        for file in &args.pattern_files {
+            // one pattern per line, as with grep -f
+            let contents = fs::read_to_string(file).map_err(|err| {
+                Args::command().error(ErrorKind::Io, format!("{file}: {err}"))
+            })?;
+            patterns.extend(contents.lines().map(str::to_string));
+        }
+
+        let (patterns, fp) = if patterns.is_empty() && args.pattern_files.is_empty() {
+            let Some(pattern) = args.pattern else {
+                return Err(Args::command().error(ErrorKind::MissingRequiredArgument, "no pattern given"));
+            };
+            (vec![pattern], args.path)
+        } else {
+            // with -e or -f, the first positional is the path
+            if args.path.is_some() {
+                return Err(Args::command().error(ErrorKind::TooManyValues, "only one PATH may be given"));
+            }
+            (patterns, args.pattern)
+        };
+        // with no file to read, search whatever is piped in
+        let fp = fp.unwrap_or_else(|| "-".to_string());
+        if args.in_place && fp == "-" {
+            return Err(Args::command().error(ErrorKind::ArgumentConflict, "--in-place needs a file or directory to rewrite"));
+        }
+        if args.index && fp == "-" {
+            return Err(Args::command().error(ErrorKind::ArgumentConflict, "--index needs a directory to search"));
+        }
+
+        let ignore_case = ignore_case(args.ignore_case, args.case_sensitive, env::var_os("IGNORE_CASE"));
+
+        // -A and -B are more specific than -C, so they win regardless of order
+        let before_context = args.before_context.or(args.context).unwrap_or(0);
+        let after_context = args.after_context.or(args.context).unwrap_or(0);
+
+        let mode = if args.count {
+            OutputMode::Count
+        } else if args.files_with_matches {
+            OutputMode::FilesWithMatches
+        } else if args.files_without_match {
+            OutputMode::FilesWithoutMatch
+        } else if args.quiet {
+            OutputMode::Quiet
+        } else {
+            OutputMode::Lines
+        };
+
+        // NO_COLOR only vetoes the automatic choice, an explicit --color=always still wins
+        let color = match args.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+        };
+
+        let types = if args.types.is_empty() && args.types_not.is_empty() {
+            None
+        } else {
+            let types = walk::types(&args.type_add, &args.types, &args.types_not).map_err(|err| {
+                Args::command().error(ErrorKind::InvalidValue, err)
+            })?;
+            Some(types)
+        };
+
+        let threads = args.threads.or_else(|| thread::available_parallelism().ok()).map_or(1, |n| n.get());
+
+        Ok(Config {
+            patterns,
+            fp,
+            regex: args.regex,
+            word_regexp: args.word_regexp,
+            line_regexp: args.line_regexp,
+            ignore_case,
+            types,
+            index: args.index,
+            hidden: args.hidden,
+            no_ignore: args.no_ignore,
+            // a multiline match is only useful with the lines it covers
+            line_number: args.line_number || args.multiline,
+            byte_offset: args.byte_offset,
+            before_context,
+            after_context,
+            threads,
+            json: args.json,
+            invert_match: args.invert_match,
+            max_count: args.max_count,
+            mode,
+            replace: args.replace,
+            in_place: args.in_place,
+            color,
+            fuzzy: args.fuzzy,
+            multiline: args.multiline,
+            search_zip: args.search_zip,
+        })
+    }
+
+    /// Runs the search. Inputs that can't be read while walking a directory
+    /// are reported on stderr and reflected in the returned `Status`; any
+    /// other failure ends the run with an error.
+    pub fn run(config: Config) -> Result<Status, Box<dyn Error>> {
+        let started = Instant::now();
+        // compile once up front, every line of every file is matched against it
+        let query = Query::new(&config)?;
+        let mut out = io::stdout().lock();
+        let root = Path::new(&config.fp);
+        let mut errors = false;
+
+        let total = if config.fp == "-" {
+            let reader = config.decode(io::stdin().lock())?;
+            config.search_input(&query, None, false, reader, &mut out)?
+        } else if !root.is_dir() {
+            // name the file, as errors from a directory walk do
+            let reader = File::open(root)
+                .and_then(|file| config.decode(BufReader::new(file)))
+                .map_err(|err| format!("{}: {err}", root.display()))?;
+            config.search_input(&query, Some(root), false, reader, &mut out)?
+        } else {
+            let options = WalkOptions { hidden: config.hidden, no_ignore: config.no_ignore, types: config.types.clone() };
+            let mut files = walk::files(root, options)?;
+            files.retain(|path| !index::is_index_file(root, path));
+            if config.index {
+                let index = Index::load(root).map_err(|err| {
+                    format!("{}: {err}; run `minigrep index build {}` first", root.join(INDEX_FILE).display(), root.display())
+                })?;
+                if let Some(query) = TrigramQuery::new(&config) {
+                    let candidates = index.candidates(&query);
+                    files.retain(|path| candidates.may_match(root, path));
+                }
+            }
+            // each file is rendered on its own, so group separators between files are added here
+            let with_context = config.mode == OutputMode::Lines && !config.json && config.replace.is_none()
+                && (config.before_context > 0 || config.after_context > 0);
+            let mut printed = false;
+            let mut total = Stats::default();
+            // in quiet mode the first match settles the exit status, so the remaining files are skipped
+            let settled = AtomicBool::new(false);
+
+            parallel::search_files(&files, config.threads, |path| {
+                let mut output = Vec::new();
+                if settled.load(Ordering::Relaxed) {
+                    return (path, output, Ok(Stats::default()));
+                }
+                let result = config.open_searchable(path).and_then(|reader| match reader {
+                    Some(reader) => config.search_input(&query, Some(path), true, reader, &mut output),
+                    None => Ok(Stats::default()),
+                });
+                if config.mode == OutputMode::Quiet && result.as_ref().is_ok_and(|stats| stats.matched_lines > 0) {
+                    settled.store(true, Ordering::Relaxed);
+                }
+                (path, output, result)
+            }, |(path, output, result)| {
+                match result {
+                    Ok(stats) => total.add(&stats),
+                    Err(err) => {
+                        eprintln!("minigrep: {}: {err}", path.display());
+                        errors = true;
+                    }
+                }
+                if output.is_empty() {
+                    return Ok(());
+                }
+                if with_context && printed {
+                    paint(&mut out, config.color, SEPARATOR, "--")?;
+                    writeln!(out)?;
+                }
+                printed = true;
+                out.write_all(&output)
+            })?;
+            total
+        };
+
+        if config.json {
+            json::summary(&mut out, &total, started.elapsed())?;
+        }
+        Ok(if errors && !(config.mode == OutputMode::Quiet && total.matched_lines > 0) {
+            Status::Error
+        } else if total.matched_lines > 0 {
+            Status::Match
+        } else {
+            Status::NoMatch
+        })
+    }
+
+    /// Opens a file found while walking a directory. Binary files are
+    /// skipped, coming back as `None`; with `search_zip`, that is decided on
+    /// the decompressed contents.
+    fn open_searchable(&self, path: &Path) -> io::Result<Option<Box<dyn BufRead>>> {
+        let mut reader = self.decode(BufReader::new(File::open(path)?))?;
+        // sniff the first buffer-full; it is kept and searched afterwards
+        if walk::is_binary(reader.fill_buf()?) {
+            return Ok(None);
+        }
+        Ok(Some(reader))
+    }
+
+    /// Decompresses `reader` when `search_zip` is set and it looks compressed.
+    fn decode<'a, R: BufRead + 'a>(&self, reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+        if self.search_zip {
+            decompress::decoder(reader)
+        } else {
+            Ok(Box::new(reader))
+        }
+    }
+
+    fn print_options(&self) -> PrintOptions {
+        PrintOptions {
+            line_number: self.line_number,
+            byte_offset: self.byte_offset,
+            before_context: self.before_context,
+            after_context: self.after_context,
+            color: self.color,
+        }
+    }
+
+    fn search_options(&self) -> SearchOptions {
+        SearchOptions { invert_match: self.invert_match, max_count: self.max_count }
+    }
+
+    /// Searches `reader` line by line, or as a whole in multiline mode.
+    /// Fuzzy matches come out ranked.
+    fn search_with<R: BufRead, S: Sink>(&self, query: &Query, options: SearchOptions, reader: R, sink: &mut S) -> io::Result<Stats> {
+        match query {
+            Query::Regex(re) if self.multiline => search_multiline(re, options, reader, sink),
+            Query::Fuzzy(_) => fuzzy::search_ranked(query, options, reader, sink),
+            _ => search_reader(query, options, reader, sink),
+        }
+    }
+
+    /// Searches one input and writes its results to `out` in the configured
+    /// format. Text output only names the file when `show_path` is set, JSON
+    /// output and file lists always do.
+    fn search_input<R: BufRead, W: Write>(&self, query: &Query, path: Option<&Path>, show_path: bool, reader: R, mut out: W) -> io::Result<Stats> {
+        let options = self.search_options();
+        // one selected line is enough to know whether an input belongs in a file list
+        let first_only = SearchOptions { max_count: Some(options.max_count.map_or(1, |max| max.min(1))), ..options };
+        let name = path.map_or_else(|| "(standard input)".to_string(), |p| p.display().to_string());
+
+        if let (OutputMode::Lines, Some(template)) = (self.mode, &self.replace) {
+            // `$` references only make sense in patterns the user wrote as regexes
+            let template = Template { text: template, expand: self.regex };
+            return match path {
+                Some(path) if self.in_place => replace::rewrite(query, template, options.max_count, path, reader),
+                _ => search_reader(query, options, reader, &mut Preview::new(out, query, template, name)),
+            };
+        }
+
+        match self.mode {
+            OutputMode::Lines if self.json => {
+                let mut printer = JsonPrinter::new(out);
+                printer.begin(path)?;
+                let stats = self.search_with(query, options, reader, &mut printer)?;
+                printer.end(&stats)?;
+                Ok(stats)
+            }
+            OutputMode::Lines => {
+                let mut printer = Printer::new(out, self.print_options());
+                printer.begin(path.filter(|_| show_path));
+                self.search_with(query, options, reader, &mut printer)
+            }
+            OutputMode::Count => {
+                let stats = self.search_with(query, options, reader, &mut Discard)?;
+                if show_path {
+                    paint(&mut out, self.color, PATH, &name)?;
+                    paint(&mut out, self.color, SEPARATOR, ':')?;
+                }
+                writeln!(out, "{}", stats.matched_lines)?;
+                Ok(stats)
+            }
+            OutputMode::FilesWithMatches | OutputMode::FilesWithoutMatch => {
+                let stats = self.search_with(query, first_only, reader, &mut Discard)?;
+                if (stats.matched_lines > 0) == (self.mode == OutputMode::FilesWithMatches) {
+                    paint(&mut out, self.color, PATH, &name)?;
+                    writeln!(out)?;
+                }
+                Ok(stats)
+            }
+            OutputMode::Quiet => self.search_with(query, first_only, reader, &mut Discard),
+        }
+    }
+}
+
+/// How a run ended, following grep's exit status convention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// At least one line was selected.
+    Match,
+    NoMatch,
+    /// Some inputs couldn't be searched.
+    Error,
+}
+
+impl Status {
+    pub fn code(self) -> i32 {
+        match self {
+            Status::Match => 0,
+            Status::NoMatch => 1,
+            Status::Error => 2,
+        }
+    }
+}
+
+/// The query from a `Config`, prepared for matching against many lines.
+pub enum Query {
+    Literal(String),
+    /// Holds the query already case-folded.
+    CaseInsensitive(String),
+    /// Several literals, or literals that must make up whole words or lines.
+    Literals(Literals),
+    /// Like `Literals`, built from case-folded patterns and run over folded lines.
+    CaseInsensitiveLiterals(Literals),
+    Regex(Regex),
+    Fuzzy(Fuzzy),
+}
+
+impl Query {
+    pub fn new(config: &Config) -> Result<Query, Box<dyn Error>> {
+        let patterns = &config.patterns;
+        if let Some(max_distance) = config.fuzzy {
+            let [pattern] = &patterns[..] else {
+                return Err("--fuzzy takes exactly one pattern".into());
+            };
+            return Ok(Query::Fuzzy(Fuzzy::new(pattern, max_distance, config.ignore_case)));
+        }
+        // searching a whole input at once takes the regex engine
+        if config.regex || config.multiline {
+            let mut alternation = patterns.iter()
+                .map(|p| match (config.regex, patterns.len()) {
+                    (false, _) => regex::escape(p),
+                    (true, 1) => p.clone(),
+                    (true, _) => format!("(?:{p})"),
+                })
+                .collect::<Vec<_>>()
+                .join("|");
+            if config.line_regexp {
+                alternation = format!("^(?:{alternation})$");
+            } else if config.word_regexp {
+                alternation = format!(r"\b(?:{alternation})\b");
+            }
+            // an empty pattern list selects nothing, unlike the empty regex
+            if patterns.is_empty() {
+                alternation = r"[^\s\S]".to_string();
+            }
+            let re = RegexBuilder::new(&alternation)
+                .case_insensitive(config.ignore_case)
+                // over a whole input, ^ and $ still mean the start and end of a line
+                .multi_line(config.multiline)
+                .crlf(config.multiline)
+                .build()?;
+            return Ok(Query::Regex(re));
+        }
+
+        if let ([pattern], false, false) = (&patterns[..], config.word_regexp, config.line_regexp) {
+            return Ok(if config.ignore_case {
+                Query::CaseInsensitive(fold_case(pattern))
+            } else {
+                Query::Literal(pattern.clone())
+            });
+        }
+
+        // literals never go through the regex engine, which chokes on an
+        // alternation of thousands of them
+        let folded: Vec<String>;
+        let patterns = if config.ignore_case {
+            folded = patterns.iter().map(|p| fold_case(p)).collect();
+            &folded
+        } else {
+            patterns
+        };
+        let literals = if config.line_regexp {
+            Literals::lines(patterns)
+        } else if config.word_regexp {
+            Literals::words(patterns)?
+        } else {
+            Literals::anywhere(patterns)?
+        };
+        Ok(if config.ignore_case { Query::CaseInsensitiveLiterals(literals) } else { Query::Literals(literals) })
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        match self {
+            Query::Literal(q) => line.contains(q.as_str()),
+            Query::CaseInsensitive(q) => fold_case(line).contains(q.as_str()),
+            Query::Literals(literals) => literals.is_match(line),
+            Query::CaseInsensitiveLiterals(literals) => literals.is_match(&fold_case(line)),
+            Query::Regex(re) => re.is_match(line),
+            Query::Fuzzy(fuzzy) => fuzzy.best_match(line).is_some(),
+        }
+    }
+
+    /// Byte ranges of every non-overlapping occurrence of the query in `line`.
+    pub fn find_spans(&self, line: &str) -> Vec<Range<usize>> {
+        match self {
+            Query::Literal(q) => line.match_indices(q.as_str()).map(|(i, m)| i..i + m.len()).collect(),
+            Query::CaseInsensitive(q) => {
+                let (folded, origins) = fold_case_indexed(line);
+                folded.match_indices(q.as_str())
+                    .map(|(i, m)| unfold_span(line, &origins, i..i + m.len()))
+                    .collect()
+            }
+            Query::Literals(literals) => literals.find_spans(line),
+            Query::CaseInsensitiveLiterals(literals) => {
+                let (folded, origins) = fold_case_indexed(line);
+                literals.find_spans(&folded).into_iter().map(|span| unfold_span(line, &origins, span)).collect()
+            }
+            Query::Regex(re) => re.find_iter(line).map(|m| m.range()).collect(),
+            // only the closest stretch, since approximate matches overlap freely
+            Query::Fuzzy(fuzzy) => fuzzy.best_match(line).map(|(_, span)| span).into_iter().collect(),
         }
+    }
+
+    /// Like `find_spans`, but also returns the capture groups of each
+    /// occurrence when the query is a regex that has any.
+    pub fn find_captures(&self, line: &str) -> (Vec<Range<usize>>, Vec<Groups>) {
+        match self {
+            Query::Regex(re) if re.captures_len() > 1 => re.captures_iter(line)
+                .map(|caps| {
+                    let groups = caps.iter().skip(1).map(|group| group.map(|g| g.range())).collect();
+                    (caps.get(0).unwrap().range(), groups)
+                })
+                .unzip(),
+            _ => (self.find_spans(line), Vec::new()),
+        }
+    }
+
+    /// `line` with every occurrence of the query replaced by `template`.
+    /// `$` references are only expanded for a regex query, and only when the
+    /// template asks for it; otherwise the text goes in as is.
+    pub fn replace_all<'a>(&self, line: &'a str, template: Template) -> Cow<'a, str> {
+        match self {
+            Query::Regex(re) if template.expand => return re.replace_all(line, template.text),
+            Query::Regex(re) => return re.replace_all(line, NoExpand(template.text)),
+            _ => {}
+        }
+
+        let spans = self.find_spans(line);
+        if spans.is_empty() {
+            return Cow::Borrowed(line);
+        }
+        let mut replaced = String::with_capacity(line.len());
+        let mut last = 0;
+        for span in spans {
+            replaced.push_str(&line[last..span.start]);
+            replaced.push_str(template.text);
+            last = span.end;
+        }
+        replaced.push_str(&line[last..]);
+        Cow::Owned(replaced)
+    }
+}
+
+/// Totals for one or more searched inputs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub searches: usize,
+    pub searches_with_match: usize,
+    pub searched_lines: usize,
+    pub matched_lines: usize,
+    pub bytes_searched: usize,
+}
+
+impl Stats {
+    pub fn add(&mut self, other: &Stats) {
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.searched_lines += other.searched_lines;
+        self.matched_lines += other.matched_lines;
+        self.bytes_searched += other.bytes_searched;
+    }
+}
+
+/// Receives the lines of a streaming search as they are read.
+pub trait Sink {
+    /// Lines selected by the search.
+    fn matched(&mut self, m: &Match) -> io::Result<()>;
+
+    /// Lines that weren't selected; only sinks that print context care about them.
+    fn unmatched(&mut self, _line: &Match) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Whether the sink still wants more lines for trailing context, which
+    /// keeps the search reading past `max_count`.
+    fn context_pending(&self) -> bool {
+        false
+    }
+}
+
+/// A sink for searches where only the returned `Stats` matter.
+struct Discard;
+
+impl Sink for Discard {
+    fn matched(&mut self, _m: &Match) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Which lines a search selects and when it stops.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SearchOptions {
+    /// Select the lines that don't match the query.
+    pub invert_match: bool,
+    /// Stop after this many selected lines.
+    pub max_count: Option<usize>,
+}
+
+/// Searches `reader` one line at a time so memory use doesn't grow with the
+/// size of the input. Lines that aren't valid UTF-8 are decoded lossily
+/// rather than ending the search.
+pub fn search_reader<R: BufRead, S: Sink>(query: &Query, options: SearchOptions, mut reader: R, sink: &mut S) -> io::Result<Stats> {
+    let mut buf = Vec::new();
+    let mut offset = 0;
+    let mut line_index = 0;
+    let mut matched_lines = 0;
+
+    loop {
+        let reached_max = options.max_count.is_some_and(|max| matched_lines >= max);
+        if reached_max && !sink.context_pending() {
+            break;
+        }
+
+        buf.clear();
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 {
+            break;
+        }
+
+        let line = buf.strip_suffix(b"\n").map_or(&buf[..], |l| l.strip_suffix(b"\r").unwrap_or(l));
+        let text = String::from_utf8_lossy(line);
+        let mut m = Match { line_index, byte_range: offset..offset + line.len(), text: &text, spans: Vec::new(), groups: Vec::new() };
+        // past max_count, lines are only read to finish off trailing context
+        if !reached_max && query.is_match(&text) != options.invert_match {
+            matched_lines += 1;
+            // an inverted selection has nothing in it to point at
+            if !options.invert_match {
+                (m.spans, m.groups) = query.find_captures(&text);
+            }
+            sink.matched(&m)?;
+        } else {
+            sink.unmatched(&m)?;
+        }
+
+        offset += read;
+        line_index += 1;
+    }
+
+    Ok(Stats {
+        searches: 1,
+        searches_with_match: usize::from(matched_lines > 0),
+        searched_lines: line_index,
+        matched_lines,
+        bytes_searched: offset,
+    })
+}
+
+/// Like `search_reader`, but runs `re` over the whole input at once so a match
+/// may span several lines. Lines touched by the same match, or by matches that
+/// share a line, are handed to the sink as one `Match` whose `text` holds all
+/// of them; `max_count` counts those. The input is read into memory and
+/// decoded lossily up front, so offsets count bytes of the decoded text.
+pub fn search_multiline<R: BufRead, S: Sink>(re: &Regex, options: SearchOptions, mut reader: R, sink: &mut S) -> io::Result<Stats> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    let contents = String::from_utf8_lossy(&buf);
+    let lines: Vec<(Range<usize>, &str)> = lines(&contents).collect();
+    // the line an offset falls on, counting its terminator as part of it
+    let line_of = |offset: usize| lines.partition_point(|(range, _)| range.start <= offset).saturating_sub(1);
+
+    // runs of line indices covered by matches, with the spans found in them
+    let mut blocks: Vec<(Range<usize>, Vec<Range<usize>>)> = Vec::new();
+    // after a final newline there is no line left for a match to be on
+    let past_end = |offset: usize| offset == contents.len() && contents.ends_with('\n');
+    for m in re.find_iter(&contents).filter(|m| !past_end(m.start())) {
+        let first = line_of(m.start());
+        let last = line_of(m.end().saturating_sub(1).max(m.start()));
+        match blocks.last_mut() {
+            Some((block, spans)) if first < block.end => {
+                block.end = block.end.max(last + 1);
+                spans.push(m.range());
+            }
+            _ => blocks.push((first..last + 1, vec![m.range()])),
+        }
+    }
+
+    let mut line_index = 0;
+    let mut selected = 0;
+    let mut matched_lines = 0;
+    let mut next_block = 0;
+
+    while let Some((byte_range, text)) = lines.get(line_index).cloned() {
+        let reached_max = options.max_count.is_some_and(|max| selected >= max);
+        if reached_max && !sink.context_pending() {
+            break;
+        }
+        while blocks.get(next_block).is_some_and(|(block, _)| block.end <= line_index) {
+            next_block += 1;
+        }
+        let block = blocks.get(next_block).filter(|(block, _)| block.contains(&line_index));
+
+        match block {
+            Some((block, spans)) if !reached_max && !options.invert_match => {
+                let start = byte_range.start;
+                let end = lines[block.end - 1].0.end;
+                let spans = spans.iter().map(|span| span.start.min(end) - start..span.end.min(end) - start).collect();
+                sink.matched(&Match { line_index, byte_range: start..end, text: &contents[start..end], spans, groups: Vec::new() })?;
+                selected += 1;
+                matched_lines += block.len();
+                line_index = block.end;
+                continue;
+            }
+            None if !reached_max && options.invert_match => {
+                sink.matched(&Match { line_index, byte_range, text, spans: Vec::new(), groups: Vec::new() })?;
+                selected += 1;
+                matched_lines += 1;
+            }
+            _ => sink.unmatched(&Match { line_index, byte_range, text, spans: Vec::new(), groups: Vec::new() })?,
+        }
+        line_index += 1;
+    }
+
+    Ok(Stats {
+        searches: 1,
+        searches_with_match: usize::from(matched_lines > 0),
+        searched_lines: line_index,
+        matched_lines,
+        bytes_searched: buf.len(),
+    })
+}
+
+/// Where each capture group of a regex matched, group 1 first; `None` for a
+/// group that took no part.
+pub type Groups = Vec<Option<Range<usize>>>;
+
+/// A line that matched the query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match<'a> {
+    /// Zero-based index of the line within the searched input.
+    pub line_index: usize,
+    /// Where the line sits in the searched input, excluding its terminator.
+    /// For lossily decoded lines this spans the original bytes, not `text`.
+    pub byte_range: Range<usize>,
+    pub text: &'a str,
+    /// Where the query occurs within `text`.
+    pub spans: Vec<Range<usize>>,
+    /// For a regex with capture groups, the groups of each of `spans`. Empty
+    /// for other queries, and in multiline mode.
+    pub groups: Vec<Groups>,
+}
+
+impl Match<'_> {
+    /// Index of the last line covered, past `line_index` only for a
+    /// multiline match, whose `text` holds every line it spans.
+    pub fn last_line_index(&self) -> usize {
+        self.line_index + self.text.matches('\n').count()
+    }
+}
+
+/// Whether to match without regard to case, given `-i`, `-s` and the value
+/// of `IGNORE_CASE`; an explicit flag always wins over the environment.
+fn ignore_case(ignore_case: bool, case_sensitive: bool, env: Option<OsString>) -> bool {
+    if ignore_case || case_sensitive {
+        ignore_case
+    } else {
+        env.is_some()
+    }
+}
+
+/// Splits `contents` the way `str::lines` does, but also yields where each
+/// line starts and ends so matches can be located in the original buffer.
+fn lines(contents: &str) -> impl Iterator<Item = (Range<usize>, &str)> {
+    contents.split_inclusive('\n').scan(0, |offset, raw| {
+        let start = *offset;
+        *offset += raw.len();
+        let line = raw.strip_suffix('\n').map_or(raw, |l| l.strip_suffix('\r').unwrap_or(l));
+        Some((start..start + line.len(), line))
+    })
+}
+
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>>{
+    search_query(&Query::Literal(query.to_string()), contents)
+}
+
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>>{
+    search_query(&Query::CaseInsensitive(fold_case(query)), contents)
+}
+
+/// Lines of `contents` containing `query` within `max_distance` edits,
+/// closest first.
+pub fn search_fuzzy<'a>(query: &str, max_distance: usize, contents: &'a str) -> Vec<Match<'a>>{
+    let fuzzy = Fuzzy::new(query, max_distance, false);
+    let mut results = Vec::new();
 
-        let q = &args[1].clone();
-        let fp = &args[2].clone();
-    
-        Ok(Config { q: q.to_string(), fp: fp.to_string() })
+    for (line_index, (byte_range, text)) in lines(contents).enumerate() {
+        if let Some((distance, span)) = fuzzy.best_match(text) {
+            results.push((distance, Match { line_index, byte_range, text, spans: vec![span], groups: Vec::new() }));
+        }
     }
 
-    pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-        let contents = fs::read_to_string(config.fp)?;
+    results.sort_by_key(|(distance, _)| *distance);
+    results.into_iter().map(|(_, m)| m).collect()
+}
+
+fn search_query<'a>(query: &Query, contents: &'a str) -> Vec<Match<'a>>{
+    let mut results = Vec::new();
 
-        for line in search(&config.q, &contents) {
-            println!("{line}");
+    for (line_index, (byte_range, text)) in lines(contents).enumerate() {
+        let (spans, groups) = query.find_captures(text);
+        if !spans.is_empty() {
+            results.push(Match { line_index, byte_range, text, spans, groups });
         }
-        return Ok(());
+    }
+
+    results
+}
+
+/// Unicode case folding. `to_lowercase` alone is not enough to compare
+/// strings caselessly: `ß` has no lowercase form distinct from itself yet
+/// folds to `ss`, and characters such as the final sigma or the long s
+/// have several lowercase spellings that should all compare equal.
+pub fn fold_case(s: &str) -> String {
+    let mut folded = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        fold_char(c, &mut folded);
+    }
+
+    folded
+}
+
+/// Maps a span found in the folded form of `line` back onto the original
+/// characters it was folded from.
+fn unfold_span(line: &str, origins: &[usize], span: Range<usize>) -> Range<usize> {
+    let origin = |i: usize| origins.get(i).copied().unwrap_or(line.len());
+    if span.is_empty() {
+        return origin(span.start)..origin(span.start);
+    }
+    let last = origin(span.end - 1);
+    let end = last + line[last..].chars().next().map_or(0, char::len_utf8);
+    origin(span.start)..end
+}
+
+/// Like `fold_case`, but also records, for every byte of the folded string,
+/// where the character it was folded from starts in `s`.
+fn fold_case_indexed(s: &str) -> (String, Vec<usize>) {
+    let mut folded = String::with_capacity(s.len());
+    let mut origins = Vec::with_capacity(s.len());
+
+    for (i, c) in s.char_indices() {
+        fold_char(c, &mut folded);
+        origins.resize(folded.len(), i);
+    }
+
+    (folded, origins)
+}
+
+fn fold_char(c: char, folded: &mut String) {
+    match c {
+        'ß' | 'ẞ' => folded.push_str("ss"),
+        'ς' => folded.push('σ'),
+        'ſ' => folded.push('s'),
+        'ϐ' => folded.push('β'),
+        'ϑ' => folded.push('θ'),
+        'ϕ' => folded.push('φ'),
+        'ϖ' => folded.push('π'),
+        'ϰ' => folded.push('κ'),
+        'ϱ' => folded.push('ρ'),
+        'ϵ' => folded.push('ε'),
+        'ẛ' => folded.push('ṡ'),
+        'ﬀ' => folded.push_str("ff"),
+        'ﬁ' => folded.push_str("fi"),
+        'ﬂ' => folded.push_str("fl"),
+        'ﬃ' => folded.push_str("ffi"),
+        'ﬄ' => folded.push_str("ffl"),
+        'ﬅ' | 'ﬆ' => folded.push_str("st"),
+        _ => folded.extend(c.to_lowercase()),
     }
 }
 
@@ -38,18 +902,316 @@ mod tests {
 Rust: 
 safe, fast, productive.
 Pick three.";
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+        let expected = Match { line_index: 1, byte_range: 7..30, text: "safe, fast, productive.", spans: vec![Range { start: 15, end: 19 }], groups: Vec::new() };
+        assert_eq!(vec![expected], search(query, contents));
     }
-}
 
-pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str>{
-    let mut results = Vec::new();
+    fn texts<'a>(matches: Vec<Match<'a>>) -> Vec<&'a str> {
+        matches.into_iter().map(|m| m.text).collect()
+    }
+
+    #[test]
+    fn crlf_offsets(){
+        let contents = "one\r\ntwo\r\nthree";
+        let results = search("t", contents);
+        assert_eq!(vec![1, 2], results.iter().map(|m| m.line_index).collect::<Vec<_>>());
+        assert_eq!(5..8, results[0].byte_range);
+        assert_eq!("three", &contents[results[1].byte_range.clone()]);
+    }
 
-    for line in contents.lines() {
-        if line.contains(query) {
-            results.push(line);
+    struct Collect(Vec<String>);
+
+    impl Sink for Collect {
+        fn matched(&mut self, m: &Match) -> io::Result<()> {
+            self.0.push(format!("{}@{}:{}", m.line_index, m.byte_range.start, m.text));
+            Ok(())
         }
     }
 
-    results
+    #[test]
+    fn streams_lossy_lines(){
+        let input: &[u8] = b"Rust:\r\nsafe, \xfffast\nPick three.";
+        let mut sink = Collect(Vec::new());
+        search_reader(&Query::Literal("fast".to_string()), SearchOptions::default(), input, &mut sink).unwrap();
+        assert_eq!(vec!["1@7:safe, \u{FFFD}fast"], sink.0);
+    }
+
+    #[test]
+    fn stats_and_spans(){
+        let input: &[u8] = b"How dreary to be somebody!\nHow public, like a frog\n";
+        let query = Query::CaseInsensitive(fold_case("how"));
+        let stats = search_reader(&query, SearchOptions::default(), input, &mut Collect(Vec::new())).unwrap();
+        assert_eq!((2, 2, 51), (stats.searched_lines, stats.matched_lines, stats.bytes_searched));
+
+        assert_eq!(vec![0..3], query.find_spans("How dreary"));
+        // the folded query spans a single ß in the original text
+        let query = Query::CaseInsensitive(fold_case("SS"));
+        assert_eq!(vec![4..6], query.find_spans("Straße"));
+    }
+
+    #[test]
+    fn invert_and_max_count(){
+        let input: &[u8] = b"frog\nbog\nlog\nfrog\n";
+        let query = Query::Literal("og".to_string());
+
+        let mut sink = Collect(Vec::new());
+        let options = SearchOptions { max_count: Some(2), ..Default::default() };
+        let stats = search_reader(&query, options, input, &mut sink).unwrap();
+        assert_eq!(vec!["0@0:frog", "1@5:bog"], sink.0);
+        // stopped without reading the rest
+        assert_eq!(2, stats.searched_lines);
+
+        let mut sink = Collect(Vec::new());
+        let options = SearchOptions { invert_match: true, ..Default::default() };
+        let stats = search_reader(&Query::Literal("fr".to_string()), options, input, &mut sink).unwrap();
+        assert_eq!(vec!["1@5:bog", "2@9:log"], sink.0);
+        assert_eq!(2, stats.matched_lines);
+    }
+
+    #[test]
+    fn multiline_blocks(){
+        let config = Config::build(["minigrep", "-U", "--regex", "-e", "a\nb", "-e", "c\nd", "-e", "^f$"]).unwrap();
+        assert!(config.line_number);
+        let input: &[u8] = b"a\nb c\nd\ne\r\nf\r\n";
+
+        // matches sharing a line merge into one block; ^ and $ still anchor lines, CRLF included
+        let mut sink = Collect(Vec::new());
+        let stats = config.search_with(&Query::new(&config).unwrap(), SearchOptions::default(), input, &mut sink).unwrap();
+        assert_eq!(vec!["0@0:a\nb c\nd", "4@11:f"], sink.0);
+        assert_eq!(4, stats.matched_lines);
+
+        let mut sink = Collect(Vec::new());
+        let options = SearchOptions { invert_match: true, max_count: Some(1) };
+        config.search_with(&Query::new(&config).unwrap(), options, input, &mut sink).unwrap();
+        assert_eq!(vec!["3@8:e"], sink.0);
+    }
+
+    #[test]
+    fn output_modes(){
+        let config = Config::build(["minigrep", "-c", "-v", "-m", "3", "frog", "poem.txt"]).unwrap();
+        assert_eq!(OutputMode::Count, config.mode);
+        assert!(config.invert_match);
+        assert_eq!(Some(3), config.max_count);
+
+        let mut out = Vec::new();
+        let query = Query::new(&config).unwrap();
+        config.search_input(&query, None, false, &b"frog\nbog\n"[..], &mut out).unwrap();
+        assert_eq!("1\n", String::from_utf8(out).unwrap());
+
+        let config = Config::build(["minigrep", "-L", "frog"]).unwrap();
+        let mut out = Vec::new();
+        let query = Query::new(&config).unwrap();
+        config.search_input(&query, Some(Path::new("bog.txt")), true, &b"bog\n"[..], &mut out).unwrap();
+        assert_eq!("bog.txt\n", String::from_utf8(out).unwrap());
+
+        assert!(Config::build(["minigrep", "-c", "-l", "frog"]).is_err());
+        assert!(Config::build(["minigrep", "--json", "-q", "frog"]).is_err());
+    }
+
+    #[test]
+    fn replace_case_insensitive(){
+        let query = Query::CaseInsensitive(fold_case("FROG"));
+        let template = Template { text: "toad", expand: false };
+        assert_eq!("a toad, a toad", query.replace_all("a Frog, a frog", template));
+        assert!(matches!(query.replace_all("a bog", template), Cow::Borrowed(_)));
+
+        assert!(Config::build(["minigrep", "--in-place", "--replace", "toad", "frog"]).is_err());
+        assert!(Config::build(["minigrep", "--in-place", "frog", "poem.txt"]).is_err());
+    }
+
+    #[test]
+    fn word_and_line_matching(){
+        let config = Config::build(["minigrep", "-w", "-e", "frog", "-e", "a.b"]).unwrap();
+        let query = Query::new(&config).unwrap();
+        assert!(query.is_match("like a frog"));
+        assert!(!query.is_match("frogs and bogs"));
+        // -w without --regex still treats patterns literally
+        assert!(query.is_match("a.b c"));
+        assert!(!query.is_match("axb"));
+
+        let config = Config::build(["minigrep", "-x", "--regex", "-F", "To.*"]).unwrap();
+        assert!(!config.regex);
+        let query = Query::new(&config).unwrap();
+        assert!(query.is_match("To.*"));
+        assert!(!query.is_match("To an admiring bog!"));
+    }
+
+    #[test]
+    fn pattern_file_literals(){
+        let path = env::temp_dir().join(format!("minigrep-patterns-{}.txt", std::process::id()));
+        fs::write(&path, "frog\nSTRASSE\nbog\n").unwrap();
+        let config = Config::build(["minigrep".as_ref(), "-i".as_ref(), "-f".as_ref(), path.as_os_str()]).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!("-", config.fp);
+
+        let query = Query::new(&config).unwrap();
+        assert!(matches!(query, Query::CaseInsensitiveLiterals(_)));
+        assert_eq!(vec![4..11, 17..21], query.find_spans("Die Straße, ein Frog"));
+        assert!(!query.is_match("How dreary to be somebody!"));
+
+        let config = Config::build(["minigrep", "-F", "-e", "frog", "-e", "frogs"]).unwrap();
+        assert_eq!(vec![2..7], Query::new(&config).unwrap().find_spans("a frogs"));
+    }
+
+    #[test]
+    fn reads_stdin_without_file(){
+        let args: Vec<String> = ["minigrep", "to"].iter().map(|s| s.to_string()).collect();
+        assert_eq!("-", Config::build(&args).unwrap().fp);
+    }
+
+    #[test]
+    fn multiple_patterns(){
+        let config = Config::build(["minigrep", "-e", "frog", "-e", "bog", "--", "-poem.txt"]).unwrap();
+        assert_eq!(vec!["frog", "bog"], config.patterns);
+        assert_eq!("-poem.txt", config.fp);
+
+        let query = Query::new(&config).unwrap();
+        assert!(query.is_match("To an admiring bog!"));
+        assert!(query.is_match("How public, like a frog"));
+        assert!(!query.is_match("I'm nobody! Who are you?"));
+
+        let err = Config::build(["minigrep", "-e", "frog", "a.txt", "b.txt"]).err().unwrap();
+        assert_eq!(ErrorKind::TooManyValues, err.kind());
+        let err = Config::build(["minigrep"]).err().unwrap();
+        assert_eq!(ErrorKind::MissingRequiredArgument, err.kind());
+    }
+
+    #[test]
+    fn context_flags(){
+        let args: Vec<String> = ["minigrep", "-C", "2", "-A", "1", "-n", "to", "poem.txt"]
+            .iter().map(|s| s.to_string()).collect();
+        let config = Config::build(&args).unwrap();
+        assert!(config.line_number);
+        assert_eq!((2, 1), (config.before_context, config.after_context));
+
+        let args: Vec<String> = ["minigrep", "-j", "3", "to", "src"]
+            .iter().map(|s| s.to_string()).collect();
+        assert_eq!(3, Config::build(&args).unwrap().threads);
+
+        let args: Vec<String> = ["minigrep", "-j", "0", "to", "src"]
+            .iter().map(|s| s.to_string()).collect();
+        assert!(Config::build(&args).is_err());
+
+        let args: Vec<String> = ["minigrep", "-A", "x", "to", "poem.txt"]
+            .iter().map(|s| s.to_string()).collect();
+        assert!(Config::build(&args).is_err());
+    }
+
+    #[test]
+    fn case_insensitive(){
+        let query = "rUsT";
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+        assert_eq!(vec!["Rust:", "Trust me."], texts(search_case_insensitive(query, contents)));
+    }
+
+    #[test]
+    fn fuzzy(){
+        let contents = "\
+I'm nobdy! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!";
+        assert_eq!(vec!["Are you nobody, too?", "I'm nobdy! Who are you?"], texts(search_fuzzy("nobody", 1, contents)));
+        assert!(Config::build(["minigrep", "--fuzzy", "1", "--regex", "nobody"]).is_err());
+    }
+
+    #[test]
+    fn case_insensitive_unicode(){
+        let contents = "\
+Die Straße ist lang.
+ΣΟΦΟΣ
+ein Fluss";
+        assert_eq!(vec!["Die Straße ist lang."], texts(search_case_insensitive("STRASSE", contents)));
+        assert_eq!(vec!["ΣΟΦΟΣ"], texts(search_case_insensitive("σοφος", contents)));
+        assert_eq!(vec!["ein Fluss"], texts(search_case_insensitive("FLUẞ", contents)));
+    }
+
+    #[test]
+    fn ignore_case_flag_overrides_env(){
+        let args = |flags: &[&str]| -> Vec<String> {
+            let mut args = vec!["minigrep".to_string()];
+            args.extend(flags.iter().map(|s| s.to_string()));
+            args.extend(["to".to_string(), "poem.txt".to_string()]);
+            args
+        };
+        // the environment is shared with tests running alongside, so it's left alone
+        let set = || Some(OsString::from("1"));
+        assert!(ignore_case(false, false, set()));
+        assert!(!ignore_case(false, false, None));
+        assert!(!ignore_case(false, true, set()));
+        assert!(ignore_case(true, false, None));
+        assert!(!Config::build(args(&["-s"])).unwrap().ignore_case);
+        assert!(Config::build(args(&["-i"])).unwrap().ignore_case);
+    }
+
+    #[test]
+    fn regex_result(){
+        let query = Query::Regex(Regex::new(r"(\w+), (x)?fast").unwrap());
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.";
+        let results = search_query(&query, contents);
+        assert_eq!(1, results.len());
+        assert_eq!("safe, fast, productive.", results[0].text);
+        assert_eq!(vec![vec![Some(0..4), None]], results[0].groups);
+    }
+
+    #[test]
+    fn regex_flag(){
+        let args: Vec<String> = ["minigrep", "--regex", "fn \\w+", "poem.txt"]
+            .iter().map(|s| s.to_string()).collect();
+        let config = Config::build(&args).unwrap();
+        assert!(config.regex);
+        assert_eq!(vec!["fn \\w+"], config.patterns);
+        assert_eq!("poem.txt", config.fp);
+    }
+
+    #[test]
+    fn open_errors_name_the_file(){
+        let path = env::temp_dir().join(format!("minigrep-missing-{}.txt", std::process::id()));
+        let config = Config::build(["minigrep".as_ref(), "frog".as_ref(), path.as_os_str()]).unwrap();
+        let err = Config::run(config).err().unwrap();
+        assert!(err.to_string().starts_with(&format!("{}: ", path.display())));
+    }
+
+    #[test]
+    fn replace_expands_only_regex_patterns(){
+        fn template(config: &Config) -> Template<'_> {
+            Template { text: config.replace.as_deref().unwrap(), expand: config.regex }
+        }
+
+        // -w and -x patterns stay literal, so their templates do too
+        let config = Config::build(["minigrep", "-w", "--replace", "[$1] toad", "frog"]).unwrap();
+        let query = Query::new(&config).unwrap();
+        assert_eq!("a [$1] toad", query.replace_all("a frog", template(&config)));
+
+        let config = Config::build(["minigrep", "--regex", "--replace", "[$1] toad", "(a) frog"]).unwrap();
+        let query = Query::new(&config).unwrap();
+        assert_eq!("[a] toad", query.replace_all("a frog", template(&config)));
+    }
+
+    #[test]
+    fn large_pattern_file(){
+        let path = env::temp_dir().join(format!("minigrep-denylist-{}.txt", std::process::id()));
+        let denylist: Vec<String> = (0..50_000).map(|i| format!("host-{i}.example")).collect();
+        fs::write(&path, denylist.join("\n")).unwrap();
+        let build = |flag: &str| {
+            let config = Config::build(["minigrep".as_ref(), flag.as_ref(), "-f".as_ref(), path.as_os_str()]).unwrap();
+            Query::new(&config).unwrap()
+        };
+
+        let words = build("-w");
+        assert_eq!(vec![8..26], words.find_spans("blocked host-49999.example"));
+        assert!(!words.is_match("blocked host-49999.examples"));
+        let words = build("-wi");
+        assert!(words.is_match("blocked HOST-123.Example, twice"));
+        let lines = build("-xi");
+        assert!(lines.is_match("Host-7.example"));
+        assert!(!lines.is_match("host-7.example "));
+        fs::remove_file(&path).unwrap();
+    }
 }
@@ -0,0 +1,110 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::ops::Range;
+use aho_corasick::{AhoCorasick, BuildError, MatchKind};
+
+/// Any number of literal patterns, possibly thousands from `-f`, matched in
+/// a single pass over each line however many there are.
+#[derive(Debug, Clone)]
+pub enum Literals {
+    /// Occurrences anywhere in the line.
+    Anywhere(AhoCorasick),
+    /// Occurrences with a word boundary at either end, as `-w` wants.
+    Words(AhoCorasick),
+    /// Lines equal to one of the patterns, as `-x` wants.
+    Lines(HashSet<String>),
+}
+
+impl Literals {
+    /// Leftmost-longest, so overlapping patterns report the widest hit.
+    pub fn anywhere(patterns: &[String]) -> Result<Literals, BuildError> {
+        let ac = AhoCorasick::builder().match_kind(MatchKind::LeftmostLongest).build(patterns)?;
+        Ok(Literals::Anywhere(ac))
+    }
+
+    /// Hits are found overlapping, since the longest one at a position may
+    /// run into the middle of a word where a shorter one stops cleanly.
+    pub fn words(patterns: &[String]) -> Result<Literals, BuildError> {
+        let ac = AhoCorasick::builder().match_kind(MatchKind::Standard).build(patterns)?;
+        Ok(Literals::Words(ac))
+    }
+
+    pub fn lines(patterns: &[String]) -> Literals {
+        Literals::Lines(patterns.iter().cloned().collect())
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        match self {
+            Literals::Anywhere(ac) => ac.is_match(line),
+            Literals::Words(ac) => ac.find_overlapping_iter(line).any(|m| is_word(line, m.range())),
+            Literals::Lines(set) => set.contains(line),
+        }
+    }
+
+    /// Byte ranges of non-overlapping occurrences in `line`, leftmost first
+    /// and the longest of those starting at the same place.
+    pub fn find_spans(&self, line: &str) -> Vec<Range<usize>> {
+        match self {
+            Literals::Anywhere(ac) => ac.find_iter(line).map(|m| m.range()).collect(),
+            Literals::Words(ac) => {
+                let mut hits: Vec<Range<usize>> = ac.find_overlapping_iter(line)
+                    .map(|m| m.range())
+                    .filter(|hit| is_word(line, hit.clone()))
+                    .collect();
+                hits.sort_by_key(|hit| (hit.start, Reverse(hit.end)));
+                let mut spans: Vec<Range<usize>> = Vec::new();
+                for hit in hits {
+                    if spans.last().is_none_or(|last| hit.start >= last.end && hit.start > last.start) {
+                        spans.push(hit);
+                    }
+                }
+                spans
+            }
+            Literals::Lines(set) if set.contains(line) => vec![Range { start: 0, end: line.len() }],
+            Literals::Lines(_) => Vec::new(),
+        }
+    }
+}
+
+/// Whether `span` of `line` starts and ends on a word boundary, the way the
+/// regex `\b` sees it: between a word character and anything else.
+fn is_word(line: &str, span: Range<usize>) -> bool {
+    is_boundary(line, span.start) && is_boundary(line, span.end)
+}
+
+fn is_boundary(line: &str, at: usize) -> bool {
+    let before = line[..at].chars().next_back().is_some_and(is_word_char);
+    let after = line[at..].chars().next().is_some_and(is_word_char);
+    before != after
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn whole_words(){
+        let words = Literals::words(&patterns(&["frog", "frogs", "frog s"])).unwrap();
+        // the longest hit, `frog s`, ends inside a word, but the shorter `frog` doesn't
+        assert_eq!(vec![Range { start: 2, end: 6 }], words.find_spans("a frog sings"));
+        assert_eq!(vec![Range { start: 2, end: 7 }], words.find_spans("a frogs."));
+        assert!(!words.is_match("frogspawn and afrog"));
+        assert!(words.is_match("über frog"));
+    }
+
+    #[test]
+    fn whole_lines(){
+        let lines = Literals::lines(&patterns(&["a frog", ""]));
+        assert_eq!(vec![Range { start: 0, end: 6 }], lines.find_spans("a frog"));
+        assert!(lines.is_match(""));
+        assert!(!lines.is_match("a frog!"));
+    }
+}
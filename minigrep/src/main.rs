@@ -1,21 +1,27 @@
 use std::env;
+use std::ffi::OsString;
 use std::process;
+use minigrep::index::IndexConfig;
 use minigrep::Config;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    
-    let config = Config::build(&args).unwrap_or_else(|err| {
-        println!("problem parsing the arguments: {err}");
-        process::exit(1);
-    });
+    let args: Vec<OsString> = env::args_os().collect();
+    if IndexConfig::requested(&args) {
+        let config = IndexConfig::build(&args).unwrap_or_else(|err| err.exit());
+        if let Err(e) = IndexConfig::run(config) {
+            eprintln!("minigrep: {e}");
+            process::exit(2);
+        }
+        return;
+    }
 
-    println!("Serching for {}", config.q);
-    println!("In file {}", config.fp);
+    let config = Config::build(args).unwrap_or_else(|err| err.exit());
 
-    if let Err(e) = Config::run(config){
-        println!("Application error: {e}");
-        process::exit(1);
+    match Config::run(config) {
+        Ok(status) => process::exit(status.code()),
+        Err(e) => {
+            eprintln!("minigrep: {e}");
+            process::exit(2);
+        }
     }
 }
-
@@ -0,0 +1,220 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use crate::{Match, Sink};
+
+/// ANSI styles, matching GNU grep's default `GREP_COLORS`.
+pub const MATCH: &str = "\x1b[1;31m";
+pub const PATH: &str = "\x1b[35m";
+pub const LINE_NUMBER: &str = "\x1b[32m";
+pub const SEPARATOR: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// Writes `text`, wrapped in `style` when `color` is set.
+pub fn paint<W: Write>(out: &mut W, color: bool, style: &str, text: impl Display) -> io::Result<()> {
+    if color {
+        write!(out, "{style}{text}{RESET}")
+    } else {
+        write!(out, "{text}")
+    }
+}
+
+/// What gets printed around and in front of each matching line.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PrintOptions {
+    pub line_number: bool,
+    pub byte_offset: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    pub color: bool,
+}
+
+/// Writes matches in grep's format: `path:line:offset:text` for matching
+/// lines, `path-line-offset-text` for context lines, and `--` between groups
+/// of lines that aren't adjacent.
+///
+/// Lines arrive one at a time through `Sink`, so only the last
+/// `before_context` lines are ever held on to.
+pub struct Printer<W: Write> {
+    out: W,
+    options: PrintOptions,
+    /// Path printed in front of every line of the current file, if any.
+    path: Option<PathBuf>,
+    /// Whether a group has been written yet, across all files, so the next
+    /// one knows to emit a separator first.
+    printed_group: bool,
+    /// Index of the last line written for the current file.
+    last: Option<usize>,
+    /// Recent unprinted lines as `(line_index, byte_offset, text)`.
+    before: VecDeque<(usize, usize, String)>,
+    /// How many more lines of trailing context are owed.
+    after: usize,
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(out: W, options: PrintOptions) -> Printer<W> {
+        Printer {
+            out,
+            options,
+            path: None,
+            printed_group: false,
+            last: None,
+            before: VecDeque::with_capacity(options.before_context),
+            after: 0,
+        }
+    }
+
+    /// Hands back the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Starts a new input. Lines are prefixed with `path` when given.
+    pub fn begin(&mut self, path: Option<&Path>) {
+        self.path = path.map(Path::to_path_buf);
+        self.last = None;
+        self.before.clear();
+        self.after = 0;
+    }
+
+    /// Writes one line, or the lines `index..=last` of a multiline match,
+    /// numbered as a range; `spans` are highlighted when colors are on.
+    fn line(&mut self, index: usize, last: usize, offset: usize, text: &str, spans: &[Range<usize>], sep: char) -> io::Result<()> {
+        let color = self.options.color;
+        if let Some(path) = &self.path {
+            paint(&mut self.out, color, PATH, path.display())?;
+            paint(&mut self.out, color, SEPARATOR, sep)?;
+        }
+        if self.options.line_number {
+            if last > index {
+                paint(&mut self.out, color, LINE_NUMBER, format_args!("{}-{}", index + 1, last + 1))?;
+            } else {
+                paint(&mut self.out, color, LINE_NUMBER, index + 1)?;
+            }
+            paint(&mut self.out, color, SEPARATOR, sep)?;
+        }
+        if self.options.byte_offset {
+            paint(&mut self.out, color, LINE_NUMBER, offset)?;
+            paint(&mut self.out, color, SEPARATOR, sep)?;
+        }
+        self.last = Some(last);
+
+        if !color {
+            return writeln!(self.out, "{text}");
+        }
+        let mut last = 0;
+        for span in spans.iter().filter(|span| !span.is_empty()) {
+            write!(self.out, "{}", &text[last..span.start])?;
+            paint(&mut self.out, color, MATCH, &text[span.clone()])?;
+            last = span.end;
+        }
+        writeln!(self.out, "{}", &text[last..])
+    }
+}
+
+impl<W: Write> Sink for Printer<W> {
+    fn matched(&mut self, m: &Match) -> io::Result<()> {
+        let with_context = self.options.before_context > 0 || self.options.after_context > 0;
+        let first = self.before.front().map_or(m.line_index, |(index, _, _)| *index);
+        let starts_group = match self.last {
+            Some(last) => first > last + 1,
+            None => self.printed_group,
+        };
+        if with_context && starts_group {
+            paint(&mut self.out, self.options.color, SEPARATOR, "--")?;
+            writeln!(self.out)?;
+        }
+
+        while let Some((index, offset, text)) = self.before.pop_front() {
+            self.line(index, index, offset, &text, &[], '-')?;
+        }
+        self.line(m.line_index, m.last_line_index(), m.byte_range.start, m.text, &m.spans, ':')?;
+        self.after = self.options.after_context;
+        self.printed_group = true;
+        Ok(())
+    }
+
+    fn context_pending(&self) -> bool {
+        self.after > 0
+    }
+
+    fn unmatched(&mut self, line: &Match) -> io::Result<()> {
+        if self.after > 0 {
+            self.after -= 1;
+            return self.line(line.line_index, line.line_index, line.byte_range.start, line.text, &[], '-');
+        }
+
+        let capacity = self.options.before_context;
+        if capacity > 0 {
+            if self.before.len() == capacity {
+                self.before.pop_front();
+            }
+            self.before.push_back((line.line_index, line.byte_range.start, line.text.to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{search_multiline, search_reader, Query, SearchOptions};
+
+    fn render(options: PrintOptions, query: &str, contents: &str) -> String {
+        let mut printer = Printer::new(Vec::new(), options);
+        let query = Query::Literal(query.to_string());
+        search_reader(&query, SearchOptions::default(), contents.as_bytes(), &mut printer).unwrap();
+        String::from_utf8(printer.out).unwrap()
+    }
+
+    #[test]
+    fn line_numbers_and_offsets(){
+        let options = PrintOptions { line_number: true, byte_offset: true, ..Default::default() };
+        assert_eq!("2:5:bog\n", render(options, "bog", "frog\nbog\n"));
+    }
+
+    #[test]
+    fn trailing_context_after_max_count(){
+        let mut printer = Printer::new(Vec::new(), PrintOptions { after_context: 1, ..Default::default() });
+        let options = SearchOptions { max_count: Some(1), ..Default::default() };
+        let query = Query::Literal("og".to_string());
+        search_reader(&query, options, &b"frog\nbog\nlog\n"[..], &mut printer).unwrap();
+        assert_eq!("frog\nbog\n", String::from_utf8(printer.out).unwrap());
+    }
+
+    #[test]
+    fn multiline_ranges(){
+        let mut printer = Printer::new(Vec::new(), PrintOptions { line_number: true, after_context: 1, ..Default::default() });
+        let re = regex::Regex::new(r"struct \w+ \{[^}]*\}").unwrap();
+        search_multiline(&re, SearchOptions::default(), &b"// point\nstruct P {\n  x: i32,\n}\nfn main() {}\n"[..], &mut printer).unwrap();
+        assert_eq!("2-4:struct P {\n  x: i32,\n}\n5-fn main() {}\n", String::from_utf8(printer.out).unwrap());
+    }
+
+    #[test]
+    fn highlights_matches(){
+        let options = PrintOptions { line_number: true, color: true, ..Default::default() };
+        assert_eq!(
+            "\x1b[32m1\x1b[0m\x1b[36m:\x1b[0ma \x1b[1;31mbog\x1b[0m, a \x1b[1;31mbog\x1b[0m\n",
+            render(options, "bog", "a bog, a bog\n"),
+        );
+    }
+
+    #[test]
+    fn context_groups(){
+        let contents = "a\nmatch\nb\nc\nd\ne\nmatch\nf\nmatch\ng\n";
+        let options = PrintOptions { line_number: true, before_context: 1, after_context: 1, ..Default::default() };
+        assert_eq!("\
+1-a
+2:match
+3-b
+--
+6-e
+7:match
+8-f
+9:match
+10-g
+", render(options, "match", contents));
+    }
+}
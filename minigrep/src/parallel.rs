@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// Runs `search` over `files` on a pool of `threads` workers. Each result is
+/// handed to `emit` strictly in the order the files were given, so output
+/// doesn't depend on which worker finished first.
+pub fn search_files<'a, T, S, E>(files: &'a [PathBuf], threads: usize, search: S, mut emit: E) -> io::Result<()>
+where
+    T: Send,
+    S: Fn(&'a Path) -> T + Sync,
+    E: FnMut(T) -> io::Result<()>,
+{
+    // workers claim files by bumping a shared cursor, so there's no queue to fill up front
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|s| {
+        for _ in 0..threads.clamp(1, files.len().max(1)) {
+            let tx = tx.clone();
+            let next = &next;
+            let search = &search;
+            s.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = files.get(i) else { break };
+                // the receiver only hangs up when emitting failed, so stop early
+                if tx.send((i, search(path))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        // results arrive in completion order; hold them back until it's their turn
+        let mut pending = BTreeMap::new();
+        let mut expected = 0;
+        for (i, result) in rx {
+            pending.insert(i, result);
+            while let Some(result) = pending.remove(&expected) {
+                expected += 1;
+                emit(result)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::{env, fs, process};
+    use crate::output::{PrintOptions, Printer};
+    use crate::{search_reader, Query, SearchOptions};
+
+    #[test]
+    fn output_follows_file_order(){
+        let root = env::temp_dir().join(format!("minigrep-parallel-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let files: Vec<PathBuf> = (0..20).map(|i| {
+            let path = root.join(format!("{i:02}.txt"));
+            // uneven sizes so workers finish out of order
+            fs::write(&path, "filler\n".repeat((20 - i) * 500) + "needle\n").unwrap();
+            path
+        }).collect();
+
+        let query = Query::Literal("needle".to_string());
+        let mut outputs = Vec::new();
+        search_files(&files, 4, |path| {
+            let mut printer = Printer::new(Vec::new(), PrintOptions::default());
+            printer.begin(Some(path));
+            let reader = BufReader::new(File::open(path).unwrap());
+            search_reader(&query, SearchOptions::default(), reader, &mut printer).unwrap();
+            printer.into_inner()
+        }, |output| {
+            outputs.push(String::from_utf8(output).unwrap());
+            Ok(())
+        }).unwrap();
+
+        let expected: Vec<String> = files.iter().map(|p| format!("{}:needle\n", p.display())).collect();
+        assert_eq!(expected, outputs);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use crate::{Match, Query, Sink, Stats};
+
+/// What `--replace` puts in place of each match.
+#[derive(Debug, Clone, Copy)]
+pub struct Template<'a> {
+    pub text: &'a str,
+    /// Whether `$1`, `${name}` and so on refer to capture groups, which only
+    /// patterns given as regexes have.
+    pub expand: bool,
+}
+
+/// Shows what `--replace` would change as a unified diff with one hunk per
+/// changed line, leaving the input alone.
+pub struct Preview<'q, W: Write> {
+    out: W,
+    query: &'q Query,
+    template: Template<'q>,
+    label: String,
+    /// The `---`/`+++` header is only written once a line actually changes.
+    header_written: bool,
+}
+
+impl<'q, W: Write> Preview<'q, W> {
+    pub fn new(out: W, query: &'q Query, template: Template<'q>, label: String) -> Preview<'q, W> {
+        Preview { out, query, template, label, header_written: false }
+    }
+}
+
+impl<W: Write> Sink for Preview<'_, W> {
+    fn matched(&mut self, m: &Match) -> io::Result<()> {
+        let replaced = self.query.replace_all(m.text, self.template);
+        if replaced == m.text {
+            return Ok(());
+        }
+
+        if !self.header_written {
+            // like git, so the preview applies with `patch -p1` from the search root
+            let label = self.label.trim_start_matches('/');
+            writeln!(self.out, "--- a/{label}")?;
+            writeln!(self.out, "+++ b/{label}")?;
+            self.header_written = true;
+        }
+        let line = m.line_index + 1;
+        writeln!(self.out, "@@ -{line} +{line} @@")?;
+        writeln!(self.out, "-{}", m.text)?;
+        writeln!(self.out, "+{replaced}")
+    }
+}
+
+/// Rewrites the file at `path`, read through `reader`, with every match
+/// replaced by `template`, on at most `max_count` lines like the preview
+/// shows; the rest of the file is copied as is. The new contents go to a temporary file beside the
+/// original which is then renamed over it, so a crash leaves either the old
+/// file or the new one, never a mix. Lines that aren't valid UTF-8 are copied
+/// through byte for byte, as are line terminators.
+pub fn rewrite<R: BufRead>(query: &Query, template: Template, max_count: Option<usize>, path: &Path, mut reader: R) -> io::Result<Stats> {
+    let temp = temp_path(path);
+    let mut writer = BufWriter::new(OpenOptions::new().write(true).create_new(true).open(&temp)?);
+
+    let result = (|| {
+        let mut buf = Vec::new();
+        let mut stats = Stats { searches: 1, ..Stats::default() };
+        let mut changed = false;
+
+        loop {
+            buf.clear();
+            let read = reader.read_until(b'\n', &mut buf)?;
+            if read == 0 {
+                break;
+            }
+            stats.searched_lines += 1;
+            stats.bytes_searched += read;
+
+            let body_len = buf.strip_suffix(b"\n").map_or(buf.len(), |l| l.strip_suffix(b"\r").unwrap_or(l).len());
+            let (body, terminator) = buf.split_at(body_len);
+            let reached_max = max_count.is_some_and(|max| stats.matched_lines >= max);
+            match std::str::from_utf8(body) {
+                Ok(text) if !reached_max && query.is_match(text) => {
+                    stats.matched_lines += 1;
+                    let replaced = query.replace_all(text, template);
+                    changed |= replaced != text;
+                    writer.write_all(replaced.as_bytes())?;
+                    writer.write_all(terminator)?;
+                }
+                _ => writer.write_all(&buf)?,
+            }
+        }
+
+        stats.searches_with_match = usize::from(stats.matched_lines > 0);
+        writer.flush()?;
+        Ok((stats, changed))
+    })();
+    // the original must be closed before it can be replaced on every platform
+    drop(reader);
+
+    match result {
+        Ok((stats, true)) => {
+            let file = writer.into_inner().map_err(|err| err.into_error())?;
+            file.set_permissions(fs::metadata(path)?.permissions())?;
+            file.sync_all()?;
+            drop(file);
+            fs::rename(&temp, path).inspect_err(|_| {
+                let _ = fs::remove_file(&temp);
+            })?;
+            sync_parent(path);
+            Ok(stats)
+        }
+        Ok((stats, false)) => {
+            drop(writer);
+            fs::remove_file(&temp)?;
+            Ok(stats)
+        }
+        Err(err) => {
+            drop(writer);
+            let _ = fs::remove_file(&temp);
+            Err(err)
+        }
+    }
+}
+
+/// A hidden sibling of `path`, so the final rename stays on one filesystem.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map_or_else(|| "minigrep".into(), |n| n.to_string_lossy());
+    path.with_file_name(format!(".{name}.minigrep-{}.tmp", process::id()))
+}
+
+/// Makes the rename itself durable. Best effort, since not every platform
+/// lets a directory be opened and synced.
+fn sync_parent(path: &Path) {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(parent) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+    use std::{env, fs};
+    use regex::Regex;
+    use crate::{search_reader, SearchOptions};
+
+    #[test]
+    fn preview_diff(){
+        let query = Query::Regex(Regex::new(r"(\w+) frog").unwrap());
+        let mut preview = Preview::new(Vec::new(), &query, Template { text: "$1 toad", expand: true }, "poem.txt".to_string());
+        let input: &[u8] = b"How dreary to be somebody!\nHow public, like a frog\n";
+        search_reader(&query, SearchOptions::default(), input, &mut preview).unwrap();
+        assert_eq!("\
+--- a/poem.txt
++++ b/poem.txt
+@@ -2 +2 @@
+-How public, like a frog
++How public, like a toad
+", String::from_utf8(preview.out).unwrap());
+    }
+
+    #[test]
+    fn rewrites_in_place(){
+        let path = env::temp_dir().join(format!("minigrep-replace-{}.txt", process::id()));
+        fs::write(&path, b"a frog\r\n\xff frog\nfrog").unwrap();
+
+        let query = Query::Literal("frog".to_string());
+        let reader = BufReader::new(File::open(&path).unwrap());
+        let stats = rewrite(&query, Template { text: "$toad", expand: false }, None, &path, reader).unwrap();
+
+        assert_eq!(2, stats.matched_lines);
+        // literal templates are used verbatim; invalid UTF-8 and CRLF survive untouched
+        assert_eq!(b"a $toad\r\n\xff frog\n$toad".to_vec(), fs::read(&path).unwrap());
+        assert!(!temp_path(&path).exists());
+
+        // like the preview, only the first `max_count` matching lines change
+        fs::write(&path, b"a frog\nfrog frog\nfrog").unwrap();
+        let reader = BufReader::new(File::open(&path).unwrap());
+        let stats = rewrite(&query, Template { text: "toad", expand: false }, Some(2), &path, reader).unwrap();
+        assert_eq!(2, stats.matched_lines);
+        assert_eq!(b"a toad\ntoad toad\nfrog".to_vec(), fs::read(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+}
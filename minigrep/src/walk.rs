@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+use ignore::types::{Types, TypesBuilder};
+use ignore::WalkBuilder;
+
+/// How many leading bytes are inspected when deciding whether a file is binary.
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+/// Which entries a directory walk is allowed to yield.
+#[derive(Debug, Default, Clone)]
+pub struct WalkOptions {
+    /// Descend into hidden directories and yield hidden files.
+    pub hidden: bool,
+    /// Disregard `.gitignore`, `.ignore` and `.git/info/exclude`.
+    pub no_ignore: bool,
+    /// Only yield files of these types, as built by `types`.
+    pub types: Option<Types>,
+}
+
+/// Builds a file type filter from the built-in registry (`rust` for `*.rs`,
+/// `toml`, `html` and many more) plus `definitions` of the form `name:glob`.
+/// Files of a `selected` type are kept and those of a `negated` type dropped;
+/// as soon as any type is selected, files of no selected type are dropped too.
+pub fn types(definitions: &[String], selected: &[String], negated: &[String]) -> Result<Types, ignore::Error> {
+    let mut builder = TypesBuilder::new();
+    builder.add_defaults();
+    for definition in definitions {
+        builder.add_def(definition)?;
+    }
+    for name in selected {
+        builder.select(name);
+    }
+    for name in negated {
+        builder.negate(name);
+    }
+    builder.build()
+}
+
+/// Recursively collects the files under `root`, honoring ignore files and
+/// skipping hidden entries unless `options` says otherwise. Entries are sorted
+/// by name so repeated runs list the tree in the same order.
+pub fn files(root: &Path, options: WalkOptions) -> Result<Vec<PathBuf>, ignore::Error> {
+    let mut builder = WalkBuilder::new(root);
+    if let Some(types) = options.types {
+        builder.types(types);
+    }
+    let walker = builder
+        .hidden(!options.hidden)
+        .ignore(!options.no_ignore)
+        .git_ignore(!options.no_ignore)
+        .git_exclude(!options.no_ignore)
+        .git_global(false)
+        // a .gitignore is meaningful even outside a checkout
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+
+    let mut files = Vec::new();
+    for entry in walker {
+        let entry = entry?;
+        if entry.file_type().is_some_and(|t| t.is_file()) {
+            files.push(entry.into_path());
+        }
+    }
+
+    Ok(files)
+}
+
+/// A file is treated as binary when a NUL byte shows up near its start, the
+/// same heuristic grep and git use.
+pub fn is_binary(contents: &[u8]) -> bool {
+    contents[..contents.len().min(BINARY_SNIFF_LEN)].contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    #[test]
+    fn skips_ignored_and_hidden(){
+        let root = env::temp_dir().join(format!("minigrep-walk-{}", process::id()));
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("src/debug.log"), "noise").unwrap();
+        fs::write(root.join("target/out.rs"), "fn out() {}").unwrap();
+        fs::write(root.join(".secret"), "hidden").unwrap();
+
+        let found = files(&root, WalkOptions::default()).unwrap();
+        assert_eq!(vec![root.join("src/main.rs")], found);
+
+        let found = files(&root, WalkOptions { hidden: true, no_ignore: true, types: None }).unwrap();
+        assert_eq!(5, found.len());
+
+        let types = types(&["log:*.log".to_string()], &["log".to_string(), "rust".to_string()], &["rust".to_string()]).unwrap();
+        let found = files(&root, WalkOptions { no_ignore: true, types: Some(types), ..Default::default() }).unwrap();
+        assert_eq!(vec![root.join("src/debug.log")], found);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn detects_binary(){
+        assert!(is_binary(b"\x7fELF\x02\x01\x01\0\0\0"));
+        assert!(!is_binary("How dreary to be somebody!".as_bytes()));
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time;
+use crate::limit::TokenBucket;
+
+/// Most banners and response headers fit well within this.
+const READ_LIMIT: usize = 4096;
+/// How we introduce ourselves to servers that expect a name.
+const CLIENT_NAME: &str = "portscanner";
+
+/// A service guessed from what an open port sent back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    pub name: &'static str,
+    pub version: Option<String>,
+}
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{} {version}", self.name),
+            None => f.write_str(self.name),
+        }
+    }
+}
+
+/// A request for services that wait for the client to speak first.
+struct Probe {
+    request: &'static [u8],
+    identify: fn(&str) -> Option<Service>,
+}
+
+const HTTP: Probe = Probe { request: b"HEAD / HTTP/1.0\r\n\r\n", identify: identify_http };
+const REDIS: Probe = Probe { request: b"PING\r\n", identify: identify_redis };
+
+/// Works out what listens behind `stream`. Whatever the server sends by
+/// itself is read first and answered the way its protocol expects (SSH's
+/// version exchange, SMTP's `EHLO`); a silent server gets the probes in turn,
+/// each on a fresh connection as most servers hang up on a request they
+/// don't understand. `timeout` bounds every read, and `limiter`, if any,
+/// paces the extra connections.
+pub async fn identify(mut stream: TcpStream, port: u16, timeout: Duration, limiter: Option<&TokenBucket>) -> Option<Service> {
+    if let Some(banner) = read(&mut stream, timeout).await {
+        return Some(identify_banner(&mut stream, &banner, timeout).await);
+    }
+
+    let peer = stream.peer_addr().ok()?;
+    // the well-known port decides which probe goes first
+    let probes = if port == 6379 { [REDIS, HTTP] } else { [HTTP, REDIS] };
+    let mut stream = Some(stream);
+    for probe in probes {
+        let mut stream = match stream.take() {
+            Some(stream) => stream,
+            None => reconnect(peer, timeout, limiter).await?,
+        };
+        stream.write_all(probe.request).await.ok()?;
+        let Some(response) = read(&mut stream, timeout).await else { continue };
+        if let Some(mut service) = (probe.identify)(&response) {
+            if service.name == "redis" && response.starts_with("+PONG") {
+                service.version = redis_version(&mut stream, timeout).await;
+            }
+            return Some(service);
+        }
+    }
+    None
+}
+
+/// Services that greet the client: SSH, SMTP and FTP, or an unknown one
+/// reported with its greeting.
+async fn identify_banner(stream: &mut TcpStream, banner: &str, timeout: Duration) -> Service {
+    let first_line = banner.lines().next().unwrap_or_default().trim();
+
+    if let Some(ident) = first_line.strip_prefix("SSH-") {
+        // complete the exchange, so the server doesn't log a client that never identified
+        let _ = stream.write_all(format!("SSH-2.0-{CLIENT_NAME}\r\n").as_bytes()).await;
+        let version = ident.split_once('-').and_then(|(_, software)| printable(software));
+        return Service { name: "ssh", version };
+    }
+
+    if let Some(greeting) = first_line.strip_prefix("220").map(|rest| rest.trim_start_matches(['-', ' '])) {
+        let _ = stream.write_all(format!("EHLO {CLIENT_NAME}\r\n").as_bytes()).await;
+        let smtp = read(stream, timeout).await.is_some_and(|reply| reply.starts_with("250"));
+        let name = if smtp { "smtp" } else if greeting.contains("FTP") { "ftp" } else { "unknown" };
+        // `220 mail.example.com ESMTP Postfix`: the software follows the protocol name
+        let version = greeting.split_once("ESMTP ").map_or(greeting, |(_, software)| software);
+        return Service { name, version: printable(version) };
+    }
+
+    Service { name: "unknown", version: printable(first_line) }
+}
+
+fn identify_http(response: &str) -> Option<Service> {
+    if !response.starts_with("HTTP/") {
+        return None;
+    }
+    let server = response.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("server"))
+        .and_then(|(_, value)| printable(value.trim()));
+    Some(Service { name: "http", version: server })
+}
+
+fn identify_redis(response: &str) -> Option<Service> {
+    // a server requiring a password still answers in its own protocol
+    let redis = response.starts_with("+PONG") || response.starts_with("-NOAUTH") || response.starts_with("-DENIED");
+    redis.then_some(Service { name: "redis", version: None })
+}
+
+async fn redis_version(stream: &mut TcpStream, timeout: Duration) -> Option<String> {
+    stream.write_all(b"INFO server\r\n").await.ok()?;
+    let info = read(stream, timeout).await?;
+    info.lines().find_map(|line| line.strip_prefix("redis_version:")).and_then(printable)
+}
+
+async fn reconnect(peer: SocketAddr, timeout: Duration, limiter: Option<&TokenBucket>) -> Option<TcpStream> {
+    if let Some(limiter) = limiter {
+        limiter.take().await;
+    }
+    time::timeout(timeout, TcpStream::connect(peer)).await.ok()?.ok()
+}
+
+/// One read of whatever the server has sent, if it sent anything in time.
+async fn read(stream: &mut TcpStream, timeout: Duration) -> Option<String> {
+    let mut buf = vec![0; READ_LIMIT];
+    let n = time::timeout(timeout, stream.read(&mut buf)).await.ok()?.ok()?;
+    (n > 0).then(|| String::from_utf8_lossy(&buf[..n]).into_owned())
+}
+
+/// `text` with control characters dropped, since it ends up in our output;
+/// `None` if nothing is left.
+fn printable(text: &str) -> Option<String> {
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn http_server_header(){
+        let response = "HTTP/1.1 200 OK\r\nDate: today\r\nserver: nginx/1.25.3\r\n\r\n";
+        assert_eq!(Some(Service { name: "http", version: Some("nginx/1.25.3".to_string()) }), identify_http(response));
+        assert_eq!(Some(Service { name: "http", version: None }), identify_http("HTTP/1.0 400 Bad Request\r\n\r\n"));
+        assert_eq!(None, identify_http("-ERR unknown command\r\n"));
+    }
+
+    #[test]
+    fn redis_replies(){
+        assert_eq!(Some(Service { name: "redis", version: None }), identify_redis("+PONG\r\n"));
+        assert_eq!(Some(Service { name: "redis", version: None }), identify_redis("-NOAUTH Authentication required.\r\n"));
+        assert_eq!(None, identify_redis("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    /// Identifies the service behind a listener that sends `greeting` and
+    /// answers whatever comes next with `reply`.
+    async fn identify_greeting(greeting: &'static [u8], reply: &'static [u8]) -> Service {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(greeting).await.unwrap();
+            let mut buf = [0; 64];
+            if stream.read(&mut buf).await.unwrap() > 0 {
+                let _ = stream.write_all(reply).await;
+            }
+        });
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let timeout = Duration::from_secs(1);
+        let banner = read(&mut stream, timeout).await.unwrap();
+        let service = identify_banner(&mut stream, &banner, timeout).await;
+        // a server still waiting to be spoken to sees the connection close
+        drop(stream);
+        server.await.unwrap();
+        service
+    }
+
+    #[tokio::test]
+    async fn greetings(){
+        let ssh = identify_greeting(b"SSH-2.0-OpenSSH_9.6\r\n", b"").await;
+        assert_eq!(Service { name: "ssh", version: Some("OpenSSH_9.6".to_string()) }, ssh);
+        let ssh = identify_greeting(b"SSH-2.0-Open\x1b[31mSSH_9\r\n", b"").await;
+        assert_eq!(Service { name: "ssh", version: Some("Open[31mSSH_9".to_string()) }, ssh);
+
+        let smtp = identify_greeting(b"220 mail.example.com ESMTP Postfix\r\n", b"250-mail.example.com\r\n").await;
+        assert_eq!(Service { name: "smtp", version: Some("Postfix".to_string()) }, smtp);
+
+        let ftp = identify_greeting(b"220 (vsFTPd 3.0.5) FTP ready\r\n", b"500 Unknown command.\r\n").await;
+        assert_eq!("ftp", ftp.name);
+
+        let unknown = identify_greeting(b"\x1b[1mwelcome\x07\r\n", b"").await;
+        assert_eq!(Service { name: "unknown", version: Some("[1mwelcome".to_string()) }, unknown);
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+
+/// Token bucket limiting how fast probes are started. Tokens refill at
+/// `rate` per second, and up to a tenth of a second's worth can be saved up,
+/// so short bursts stay small while the long-run rate is exact. It can be
+/// shared, so every connection a scan makes draws from the same bucket.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u32) -> TokenBucket {
+        let rate = f64::from(rate);
+        let capacity = (rate / 10.0).max(1.0);
+        TokenBucket { rate, capacity, state: Mutex::new(State { tokens: capacity, refilled: Instant::now() }) }
+    }
+
+    /// Waits until a token is available and takes it.
+    pub async fn take(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                state.tokens = (state.tokens + now.duration_since(state.refilled).as_secs_f64() * self.rate).min(self.capacity);
+                state.refilled = now;
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+                (1.0 - state.tokens) / self.rate
+            };
+            time::sleep(Duration::from_secs_f64(wait)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn bursts_then_paces(){
+        // a tenth of a second's worth is available at once, the rest comes at the rate
+        let bucket = TokenBucket::new(100);
+        let start = Instant::now();
+        for _ in 0..10 {
+            bucket.take().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+        for _ in 0..5 {
+            bucket.take().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(45));
+    }
+
+    #[test]
+    fn small_rates_still_burst_one(){
+        let bucket = TokenBucket::new(3);
+        assert_eq!(1.0, bucket.capacity);
+        assert_eq!(1.0, bucket.state.lock().unwrap().tokens);
+    }
+}
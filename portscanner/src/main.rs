@@ -1,75 +1,342 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
 use std::net::IpAddr;
-use tokio::net::{TcpStream};
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::sync::mpsc::{self};
+use tokio::sync::Semaphore;
 use tokio::runtime::Runtime;
-use clap::Parser;
+use tokio::task::JoinSet;
+use tokio::time;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use limit::TokenBucket;
+use output::{Format, Output, Record, ScanInfo};
+use ports::PortSet;
+use probe::{Policy, PortState, Protocol};
+use progress::Progress;
+use targets::{Spec, Targets};
+
+mod banner;
+mod limit;
+mod output;
+mod ports;
+mod probe;
+mod progress;
+mod targets;
+mod udp;
+
+/// Results that can queue up before probes wait for them to be printed.
+const RESULTS_BUFFER: usize = 1024;
+/// How often the progress line is redrawn.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What the reporter hears from the scan.
+#[derive(Debug)]
+enum Event {
+    /// Scanning of another address starts.
+    Target(IpAddr),
+    Probed { addr: IpAddr, port: u16, result: io::Result<Record> },
+}
 
 #[derive(Debug, Parser)]
 struct Args {
-    #[arg(conflicts_with("cidr"), required_unless_present("cidr"))]
-    addr: Option<IpAddr>,
-    #[arg(long)]
-    cidr: Option<cidr::IpCidr>,
+    /// Addresses, networks (`10.0.0.0/24`), address ranges (`10.0.0.1-50`)
+    /// and hostnames to scan.
+    #[arg(value_name = "TARGET", value_parser = Spec::parse, required_unless_present = "input_list")]
+    targets: Vec<Spec>,
+    /// Read more targets from FILE, separated by whitespace, with `#`
+    /// starting a comment; `-` reads standard input. nmap's `-iL` works too.
+    #[arg(long, value_name = "FILE")]
+    input_list: Option<PathBuf>,
+    /// Targets to leave out, separated by commas.
+    #[arg(long, value_name = "TARGETS", value_parser = Spec::parse, value_delimiter = ',')]
+    exclude: Vec<Spec>,
+    /// Ports to scan: numbers, ranges and service names, such as
+    /// `22,80,443,8000-8100,ssh,http`.
+    #[arg(short = 'p', long, value_name = "PORTS", value_parser = PortSet::parse, conflicts_with_all = ["top_ports", "port_start", "port_end"])]
+    ports: Option<PortSet>,
+    /// Scan the N ports most often found open, for TCP or UDP as chosen; up
+    /// to 100 for TCP and 20 for UDP.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..), conflicts_with_all = ["port_start", "port_end"])]
+    top_ports: Option<u16>,
+    /// Ports to leave out, written like `--ports`.
+    #[arg(long, value_name = "PORTS", value_parser = PortSet::parse)]
+    exclude_ports: Option<PortSet>,
     /// Start of the range.
-    #[arg(short = 's', long, default_value_t = 1)]
+    #[arg(short = 's', long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
     port_start: u16,
     /// End of the range of ports to scan (inclusive).
-    #[arg(short = 'e', long, default_value_t = 10000)]
+    #[arg(short = 'e', long, default_value_t = 10000, value_parser = clap::value_parser!(u16).range(1..))]
     port_end: u16,
+    /// Maximum number of connection attempts in flight at once.
+    #[arg(short = 'c', long, value_name = "N", default_value = "512")]
+    concurrency: NonZeroUsize,
+    /// Maximum number of connection attempts started per second, retries
+    /// and banner probes included.
+    #[arg(long, value_name = "PPS")]
+    rate: Option<NonZeroU32>,
+    /// How long to wait for each connection attempt, in milliseconds.
+    #[arg(long, value_name = "MS", default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    timeout: u64,
+    /// How many times to retry a port that didn't answer, waiting twice as
+    /// long before each retry, starting at 100ms.
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    retries: u32,
+    /// Read what open ports send, probing with HTTP and Redis requests if
+    /// they stay silent, to guess which service and version listens there.
+    #[arg(long, conflicts_with = "udp")]
+    banners: bool,
+    /// Scan UDP ports instead of TCP ones. Well-known ports get a request in
+    /// their protocol (DNS, NTP, SNMP), others an empty datagram.
+    #[arg(long)]
+    udp: bool,
+    /// How to write results.
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = Format::Text)]
+    output_format: Format,
+    /// Write results to FILE instead of standard output.
+    #[arg(short = 'o', long, value_name = "FILE")]
+    output: Option<PathBuf>,
 }
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
-    assert_ne!(args.port_start, 0);
-    assert!(args.port_start <= args.port_end);
+    // clap has no multi-letter short options
+    let args = Args::parse_from(env::args_os().map(|arg| if arg == "-iL" { "--input-list".into() } else { arg }));
 
     let rt = Runtime::new()?;
 
-    let (tx, mut rx) = mpsc::channel(10);
+    let policy = Policy { timeout: Duration::from_millis(args.timeout), retries: args.retries };
+    let banners = args.banners;
+    let protocol = if args.udp { Protocol::Udp } else { Protocol::Tcp };
+    let mut ports = match (&args.ports, args.top_ports) {
+        (Some(ports), _) => ports.clone(),
+        (None, Some(n)) => PortSet::top(protocol, n.into())
+            .unwrap_or_else(|err| Args::command().error(ErrorKind::ValueValidation, err).exit()),
+        (None, None) if args.port_start <= args.port_end => PortSet::range(args.port_start, args.port_end),
+        (None, None) => Args::command().error(ErrorKind::ValueValidation, "--port-start is past --port-end").exit(),
+    };
+    if let Some(excluded) = &args.exclude_ports {
+        ports.remove(excluded);
+    }
+    if ports.is_empty() {
+        Args::command().error(ErrorKind::ValueValidation, "every port is excluded, so there is nothing to scan").exit();
+    }
+    let n_ports = ports.len() as u64;
+    let mut specs = args.targets.clone();
+    if let Some(path) = &args.input_list {
+        specs.extend(read_targets(path)?);
+    }
+    let targets = rt.block_on(Targets::resolve(specs, args.exclude.clone()))?;
+    let n_addresses = targets.count();
+    if n_addresses == 0 {
+        return Err("no targets left to scan".into());
+    }
+    let progress = Progress::new(n_addresses.saturating_mul(n_ports), io::stderr().is_terminal());
+
+    let out: Box<dyn Write + Send> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout()),
+    };
+    let info = ScanInfo {
+        // args_os, since a path needn't be valid Unicode
+        args: env::args_os().map(|arg| arg.to_string_lossy().into_owned()).collect::<Vec<_>>().join(" "),
+        protocol,
+        ports: ports.clone(),
+        start: SystemTime::now(),
+    };
+    let output = Output::new(out, args.output_format, info)?;
+
     rt.block_on( async {
-        let n_tasks_per_network = (args.port_end - args.port_start) as usize;
-        let mut tasks:  Vec<_> = Vec::with_capacity(n_tasks_per_network);
-
-        let (mut from_single, mut from_cidr);
-
-        let addresses: &mut dyn Iterator<Item = IpAddr> = if let Some(addr) = args.addr {
-            from_single = vec![addr].into_iter();
-            &mut from_single
-        } else if let Some(network) = args.cidr {
-            from_cidr = network.iter().map(|network| network.address());
-            &mut from_cidr
-        } else {
-            unreachable!()
-        };
-
-        for addr in addresses {
-            println!("? {addr}: {} - {}", args.port_start, args.port_end);
-            for port in args.port_start..=args.port_end {
+        let (tx, rx) = mpsc::channel(RESULTS_BUFFER);
+        // results are printed while the scan is still going, rather than all at the end
+        let reporter = tokio::spawn(report(rx, progress, output));
+
+        let semaphore = Arc::new(Semaphore::new(args.concurrency.get()));
+        let limiter = args.rate.map(|rate| Arc::new(TokenBucket::new(rate.get())));
+        let mut tasks = JoinSet::new();
+
+        // the reporter only hangs up when writing output failed, and then
+        // there is no point in scanning any further
+        'scan: for addr in targets.iter() {
+            if tx.send(Event::Target(addr)).await.is_err() {
+                break;
+            }
+            for port in ports.iter() {
+                // waiting for a permit before spawning keeps the number of tasks bounded as well
+                let permit = semaphore.clone().acquire_owned().await.unwrap();
+                if tx.is_closed() {
+                    break 'scan;
+                }
+                if let Some(limiter) = &limiter {
+                    limiter.take().await;
+                }
                 let tx = tx.clone();
-                let task = tokio::spawn( async move {
-                   if let Err(err) = scan(addr, port, tx).await {
-                       eprintln!("error: {err}")
-                   }
+                let limiter = limiter.clone();
+                tasks.spawn( async move {
+                   scan(addr, port, protocol, policy, banners, limiter, tx).await;
+                   drop(permit);
                 });
 
-                tasks.push(task);
+                // reap finished tasks as we go, a large scan would otherwise hold on to all of them
+                while let Some(result) = tasks.try_join_next() {
+                    result.unwrap();
+                }
             }
         }
-        for task in tasks {
-            task.await.unwrap();
+        if tx.is_closed() {
+            tasks.shutdown().await;
         }
-    });
+        while let Some(result) = tasks.join_next().await {
+            result.unwrap();
+        }
+        drop(tx);
+        reporter.await.unwrap()
+    })?;
 
-    drop(tx);
+    Ok(())
+}
 
-    while let Ok((addr, port)) = rx.try_recv() {
-        println!("= {addr} : {port}")
+/// The targets listed in the file at `path`, or on standard input for `-`.
+fn read_targets(path: &Path) -> Result<Vec<Spec>, Box<dyn std::error::Error>> {
+    let text = if path.as_os_str() == "-" {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        text
+    } else {
+        fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?
+    };
+    let mut specs = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split_once('#').map_or(line, |(line, _)| line);
+        for entry in line.split_whitespace() {
+            specs.push(Spec::parse(entry).map_err(|err| format!("{}:{}: {err}", path.display(), number + 1))?);
+        }
     }
-    Ok(())
+    Ok(specs)
+}
+
+async fn scan(
+    addr: IpAddr,
+    port: u16,
+    protocol: Protocol,
+    policy: Policy,
+    banners: bool,
+    limiter: Option<Arc<TokenBucket>>,
+    events_tx: mpsc::Sender<Event>,
+) {
+    let start = SystemTime::now();
+    let answer = match protocol {
+        Protocol::Tcp => probe::probe(addr, port, policy, limiter.as_deref()).await,
+        Protocol::Udp => udp::probe(addr, port, policy, limiter.as_deref()).await,
+    };
+    let result = match answer {
+        Ok(answer) => {
+            let service = match answer.stream {
+                Some(stream) if banners => banner::identify(stream, port, policy.timeout, limiter.as_deref()).await,
+                _ => None,
+            };
+            Ok(Record { addr, port, state: answer.state, latency: answer.latency, service, start, end: SystemTime::now() })
+        }
+        Err(err) => Err(err),
+    };
+    // the receiver only goes away once every probe is done, or writing output failed
+    let _ = events_tx.send(Event::Probed { addr, port, result }).await;
 }
 
-async fn scan(addr: IpAddr, port: u16, results_tx: mpsc::Sender<(IpAddr, u16)>) -> Result<(), mpsc::error::SendError<(IpAddr, u16)>> {
-    if let Ok(_ping) = TcpStream::connect((addr, port)).await {
-        results_tx.send((addr, port)).await?;
+/// Writes each result to `output` as soon as it comes in, keeping the
+/// progress line up to date in between.
+async fn report<W: Write>(mut events_rx: mpsc::Receiver<Event>, mut progress: Progress, mut output: Output<W>) -> io::Result<()> {
+    let mut ticker = time::interval(PROGRESS_INTERVAL);
+
+    loop {
+        tokio::select! {
+            event = events_rx.recv() => match event {
+                Some(Event::Target(addr)) => {
+                    progress.clear();
+                    output.target(addr)?;
+                }
+                Some(Event::Probed { addr, port, result }) => {
+                    progress.advance();
+                    match result {
+                        Ok(record) => {
+                            // closed ports are only counted, so there's no line to make room for
+                            if record.state != PortState::Closed {
+                                progress.clear();
+                            }
+                            output.record(record)?;
+                        }
+                        Err(err) => {
+                            progress.clear();
+                            eprintln!("error: {addr}:{port}: {err}");
+                        }
+                    }
+                }
+                None => break,
+            },
+            _ = ticker.tick() => progress.draw(),
+        }
+    }
+
+    progress.clear();
+    output.finish(SystemTime::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Output the test can look at while `report` still writes to it.
+    #[derive(Debug, Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Shared {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn probed(addr: IpAddr, port: u16, state: PortState) -> Event {
+        let now = SystemTime::now();
+        let record = Record { addr, port, state, latency: Some(Duration::from_millis(1)), service: None, start: now, end: now };
+        Event::Probed { addr, port, result: Ok(record) }
+    }
+
+    #[tokio::test]
+    async fn reports_results_as_they_arrive(){
+        let out = Shared::default();
+        let info = ScanInfo { args: "portscanner".to_string(), protocol: Protocol::Tcp, ports: PortSet::range(22, 23), start: SystemTime::now() };
+        let output = Output::new(out.clone(), Format::Text, info).unwrap();
+        let (tx, rx) = mpsc::channel(4);
+        let reporter = tokio::spawn(report(rx, Progress::new(2, false), output));
+
+        let addr = "127.0.0.1".parse().unwrap();
+        tx.send(Event::Target(addr)).await.unwrap();
+        tx.send(probed(addr, 22, PortState::Open)).await.unwrap();
+        let written = time::timeout(Duration::from_secs(5), async {
+            while !out.text().contains("= 127.0.0.1 : 22 open") {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        written.await.expect("the open port wasn't reported while the scan went on");
+        assert!(!out.text().contains('#'));
+
+        // the summary only comes once every probe hung up
+        tx.send(probed(addr, 23, PortState::Closed)).await.unwrap();
+        drop(tx);
+        reporter.await.unwrap().unwrap();
+        assert_eq!("? 127.0.0.1: 22-23\n= 127.0.0.1 : 22 open\n# 1 open, 1 closed, 0 filtered\n", out.text());
     }
-    Ok(())
 }
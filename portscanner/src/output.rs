@@ -0,0 +1,450 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use crate::banner::Service;
+use crate::ports::PortSet;
+use crate::probe::{PortState, Protocol};
+
+/// How results are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// `= addr : port state service` lines, as they come in.
+    Text,
+    /// One JSON object per line, as they come in, and a closing summary.
+    Json,
+    /// Comma-separated values with a header row, one row per port as they
+    /// come in; the summary goes to stderr, so every row is alike.
+    Csv,
+    /// The XML that `nmap -oX` writes, once the scan is done.
+    NmapXml,
+}
+
+/// What was found about one port.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub addr: IpAddr,
+    pub port: u16,
+    pub state: PortState,
+    /// Round trip of the connection attempt that settled the state; unknown
+    /// for filtered ports.
+    pub latency: Option<Duration>,
+    pub service: Option<Service>,
+    /// When probing the port started and ended, banner grabbing included.
+    pub start: SystemTime,
+    pub end: SystemTime,
+}
+
+/// The whole scan, as far as output needs to know.
+#[derive(Debug, Clone)]
+pub struct ScanInfo {
+    /// The command line, which nmap's XML records.
+    pub args: String,
+    pub protocol: Protocol,
+    pub ports: PortSet,
+    pub start: SystemTime,
+}
+
+/// How many ports ended up in each state.
+#[derive(Debug, Default)]
+struct Counts {
+    open: u64,
+    closed: u64,
+    filtered: u64,
+    open_filtered: u64,
+}
+
+impl Counts {
+    fn get(&self, state: PortState) -> u64 {
+        match state {
+            PortState::Open => self.open,
+            PortState::Closed => self.closed,
+            PortState::Filtered => self.filtered,
+            PortState::OpenFiltered => self.open_filtered,
+        }
+    }
+}
+
+/// Open, closed and filtered ports of one host.
+#[derive(Debug, Default)]
+struct Host {
+    /// Ports that get an entry of their own, which closed ones don't.
+    listed: Vec<Record>,
+    closed: u64,
+    start: Option<SystemTime>,
+    end: Option<SystemTime>,
+}
+
+/// Writes records in the chosen `Format`. Closed ports are only counted, as
+/// they make up most of any scan. Formats that can stream do so; nmap's XML
+/// groups ports by host, so it is held back until `finish`.
+pub struct Output<W: Write> {
+    out: W,
+    format: Format,
+    info: ScanInfo,
+    hosts: BTreeMap<IpAddr, Host>,
+    counts: Counts,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(mut out: W, format: Format, info: ScanInfo) -> io::Result<Output<W>> {
+        if format == Format::Csv {
+            writeln!(out, "address,port,protocol,state,latency_ms,service,version,start,end,scan_start")?;
+        }
+        Ok(Output { out, format, info, hosts: BTreeMap::new(), counts: Counts::default() })
+    }
+
+    /// Announces that scanning of `addr` starts.
+    pub fn target(&mut self, addr: IpAddr) -> io::Result<()> {
+        if self.format == Format::Text {
+            writeln!(self.out, "? {addr}: {}", self.info.ports)?;
+        }
+        Ok(())
+    }
+
+    pub fn record(&mut self, record: Record) -> io::Result<()> {
+        match record.state {
+            PortState::Open => self.counts.open += 1,
+            PortState::Closed => self.counts.closed += 1,
+            PortState::Filtered => self.counts.filtered += 1,
+            PortState::OpenFiltered => self.counts.open_filtered += 1,
+        }
+        let listed = record.state != PortState::Closed;
+
+        match self.format {
+            Format::Text if listed => match &record.service {
+                Some(service) => writeln!(self.out, "= {} : {} {} {service}", record.addr, record.port, record.state)?,
+                None => writeln!(self.out, "= {} : {} {}", record.addr, record.port, record.state)?,
+            },
+            Format::Json if listed => {
+                let service = record.service.as_ref().map(|s| json!({ "name": s.name, "version": s.version }));
+                let line = json!({
+                    "type": "port",
+                    "address": record.addr,
+                    "port": record.port,
+                    "protocol": self.info.protocol.to_string(),
+                    "state": record.state.to_string(),
+                    "latency_ms": record.latency.map(|l| l.as_secs_f64() * 1000.0),
+                    "service": service,
+                    "start": timestamp(record.start),
+                    "end": timestamp(record.end),
+                    "scan_start": timestamp(self.info.start),
+                });
+                serde_json::to_writer(&mut self.out, &line)?;
+                writeln!(self.out)?;
+            }
+            Format::Csv if listed => {
+                let (name, version) = record.service.as_ref().map_or(("", ""), |s| (s.name, s.version.as_deref().unwrap_or_default()));
+                writeln!(
+                    self.out,
+                    "{},{},{},{},{},{},{},{:.3},{:.3},{:.3}",
+                    record.addr,
+                    record.port,
+                    self.info.protocol,
+                    record.state,
+                    record.latency.map_or(String::new(), |l| format!("{:.3}", l.as_secs_f64() * 1000.0)),
+                    csv_field(name),
+                    csv_field(version),
+                    timestamp(record.start),
+                    timestamp(record.end),
+                    timestamp(self.info.start),
+                )?;
+            }
+            Format::NmapXml => {
+                let host = self.hosts.entry(record.addr).or_default();
+                host.start = Some(host.start.map_or(record.start, |start| start.min(record.start)));
+                host.end = Some(host.end.map_or(record.end, |end| end.max(record.end)));
+                if listed {
+                    host.listed.push(record);
+                } else {
+                    host.closed += 1;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Completes the output once every port is in.
+    pub fn finish(mut self, end: SystemTime) -> io::Result<()> {
+        let info = &self.info;
+        let states = states(info.protocol);
+        match self.format {
+            Format::Text => writeln!(self.out, "# {}", self.summary())?,
+            Format::Json => {
+                let counts: serde_json::Map<String, Value> = states.iter()
+                    .map(|&state| (state.to_string(), self.counts.get(state).into()))
+                    .collect();
+                let line = json!({
+                    "type": "summary",
+                    "protocol": info.protocol.to_string(),
+                    "scan_start": timestamp(info.start),
+                    "scan_end": timestamp(end),
+                    "counts": counts,
+                });
+                serde_json::to_writer(&mut self.out, &line)?;
+                writeln!(self.out)?;
+            }
+            Format::Csv => eprintln!("{}", self.summary()),
+            Format::NmapXml => self.write_xml(end)?,
+        }
+        self.out.flush()
+    }
+
+    /// How many ports ended up in each state: `2 open, 0 closed, 1 filtered`.
+    fn summary(&self) -> String {
+        let counts: Vec<String> = states(self.info.protocol).iter()
+            .map(|&state| format!("{} {state}", self.counts.get(state)))
+            .collect();
+        counts.join(", ")
+    }
+
+    fn write_xml(&mut self, end: SystemTime) -> io::Result<()> {
+        // results came in as probes finished
+        for host in self.hosts.values_mut() {
+            host.listed.sort_by_key(|record| record.port);
+        }
+        let info = &self.info;
+        let out = &mut self.out;
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(out, "<!DOCTYPE nmaprun>")?;
+        writeln!(
+            out,
+            r#"<nmaprun scanner="portscanner" args="{}" start="{}" version="{}" xmloutputversion="1.05">"#,
+            xml_escape(&info.args), unix_secs(info.start), env!("CARGO_PKG_VERSION"),
+        )?;
+        let protocol = info.protocol;
+        let scan_type = match protocol {
+            Protocol::Tcp => "connect",
+            Protocol::Udp => "udp",
+        };
+        writeln!(
+            out,
+            r#"<scaninfo type="{scan_type}" protocol="{protocol}" numservices="{}" services="{}"/>"#,
+            info.ports.len(), info.ports,
+        )?;
+
+        for (addr, host) in &self.hosts {
+            let (start, end) = (host.start.unwrap_or(info.start), host.end.unwrap_or(end));
+            writeln!(out, r#"<host starttime="{}" endtime="{}">"#, unix_secs(start), unix_secs(end))?;
+            // any answer, even a refusal, shows the host is there
+            let (state, reason) = if host.listed.iter().any(|r| r.state == PortState::Open) {
+                ("up", open_reason(protocol))
+            } else if host.closed > 0 {
+                ("up", closed_reason(protocol))
+            } else {
+                ("unknown", "no-response")
+            };
+            writeln!(out, r#"<status state="{state}" reason="{reason}"/>"#)?;
+            let addrtype = if addr.is_ipv4() { "ipv4" } else { "ipv6" };
+            writeln!(out, r#"<address addr="{addr}" addrtype="{addrtype}"/>"#)?;
+            writeln!(out, "<ports>")?;
+            if host.closed > 0 {
+                writeln!(out, r#"<extraports state="closed" count="{}"/>"#, host.closed)?;
+            }
+            for record in &host.listed {
+                let reason = if record.state == PortState::Open { open_reason(protocol) } else { "no-response" };
+                write!(out, r#"<port protocol="{protocol}" portid="{}"><state state="{}" reason="{reason}" reason_ttl="0"/>"#, record.port, record.state)?;
+                if let Some(service) = &record.service {
+                    write!(out, r#"<service name="{}""#, xml_escape(service.name))?;
+                    if let Some(version) = &service.version {
+                        write!(out, r#" product="{}""#, xml_escape(version))?;
+                    }
+                    write!(out, r#" method="probed" conf="10"/>"#)?;
+                }
+                writeln!(out, "</port>")?;
+            }
+            writeln!(out, "</ports>")?;
+            // nmap gives round trip times per host, in microseconds
+            let latencies: Vec<Duration> = host.listed.iter().filter_map(|r| r.latency).collect();
+            if !latencies.is_empty() {
+                let srtt = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+                writeln!(out, r#"<times srtt="{}" rttvar="0" to="{}"/>"#, srtt.as_micros(), srtt.as_micros() * 4)?;
+            }
+            writeln!(out, "</host>")?;
+        }
+
+        let up = self.hosts.values().filter(|h| h.closed > 0 || h.listed.iter().any(|r| r.state == PortState::Open)).count();
+        let elapsed = end.duration_since(info.start).unwrap_or_default().as_secs_f64();
+        writeln!(out, "<runstats>")?;
+        writeln!(out, r#"<finished time="{}" elapsed="{elapsed:.2}" exit="success"/>"#, unix_secs(end))?;
+        writeln!(out, r#"<hosts up="{up}" down="{}" total="{}"/>"#, self.hosts.len() - up, self.hosts.len())?;
+        writeln!(out, "</runstats>")?;
+        writeln!(out, "</nmaprun>")
+    }
+}
+
+/// The states a port scanned over `protocol` can end up in.
+fn states(protocol: Protocol) -> &'static [PortState] {
+    match protocol {
+        Protocol::Tcp => &[PortState::Open, PortState::Closed, PortState::Filtered],
+        Protocol::Udp => &[PortState::Open, PortState::Closed, PortState::Filtered, PortState::OpenFiltered],
+    }
+}
+
+/// nmap's name for the answer that shows a port is open.
+fn open_reason(protocol: Protocol) -> &'static str {
+    match protocol {
+        Protocol::Tcp => "syn-ack",
+        Protocol::Udp => "udp-response",
+    }
+}
+
+/// nmap's name for the answer that shows a port is closed.
+fn closed_reason(protocol: Protocol) -> &'static str {
+    match protocol {
+        Protocol::Tcp => "conn-refused",
+        Protocol::Udp => "port-unreach",
+    }
+}
+
+/// Seconds since the epoch, with milliseconds.
+fn timestamp(time: SystemTime) -> f64 {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    (since.as_millis() as f64) / 1000.0
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Quotes a field that would otherwise break the row apart.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Escapes `text` for an attribute value, dropping the control characters
+/// XML 1.0 doesn't allow at all.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\0'..='\x1f' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn info(protocol: Protocol) -> ScanInfo {
+        ScanInfo { args: "portscanner -p 1-100 <&\"x\">".to_string(), protocol, ports: PortSet::range(1, 100), start: at(1000) }
+    }
+
+    fn record(port: u16, state: PortState, service: Option<Service>) -> Record {
+        let latency = (state != PortState::Filtered).then(|| Duration::from_millis(2));
+        let addr = "10.0.0.1".parse().unwrap();
+        Record { addr, port, state, latency, service, start: at(1001), end: at(1002) }
+    }
+
+    fn write(format: Format, protocol: Protocol, records: Vec<Record>) -> String {
+        let mut out = Vec::new();
+        let mut output = Output::new(&mut out, format, info(protocol)).unwrap();
+        output.target("10.0.0.1".parse().unwrap()).unwrap();
+        for record in records {
+            output.record(record).unwrap();
+        }
+        output.finish(at(1010)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn records() -> Vec<Record> {
+        let ssh = Service { name: "ssh", version: Some("OpenSSH_9.6, \"p1\"".to_string()) };
+        vec![
+            record(80, PortState::Open, None),
+            record(22, PortState::Open, Some(ssh)),
+            record(23, PortState::Closed, None),
+            record(25, PortState::Closed, None),
+            record(53, PortState::Filtered, None),
+        ]
+    }
+
+    #[test]
+    fn text(){
+        let text = write(Format::Text, Protocol::Tcp, records());
+        assert_eq!(vec![
+            "? 10.0.0.1: 1-100",
+            "= 10.0.0.1 : 80 open",
+            "= 10.0.0.1 : 22 open ssh OpenSSH_9.6, \"p1\"",
+            "= 10.0.0.1 : 53 filtered",
+            "# 2 open, 2 closed, 1 filtered",
+        ], text.lines().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn csv_rows_are_alike(){
+        let csv = write(Format::Csv, Protocol::Tcp, records());
+        assert_eq!(vec![
+            "address,port,protocol,state,latency_ms,service,version,start,end,scan_start",
+            "10.0.0.1,80,tcp,open,2.000,,,1001.000,1002.000,1000.000",
+            "10.0.0.1,22,tcp,open,2.000,ssh,\"OpenSSH_9.6, \"\"p1\"\"\",1001.000,1002.000,1000.000",
+            "10.0.0.1,53,tcp,filtered,,,,1001.000,1002.000,1000.000",
+        ], csv.lines().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn json_ends_with_a_summary(){
+        let json = write(Format::Json, Protocol::Udp, vec![record(161, PortState::OpenFiltered, None)]);
+        let lines: Vec<Value> = json.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(2, lines.len());
+        assert_eq!(json!("port"), lines[0]["type"]);
+        assert_eq!(json!("open|filtered"), lines[0]["state"]);
+        assert_eq!(json!(1000.0), lines[0]["scan_start"]);
+        assert_eq!(json!({
+            "type": "summary",
+            "protocol": "udp",
+            "scan_start": 1000.0,
+            "scan_end": 1010.0,
+            "counts": { "open": 0, "closed": 0, "filtered": 0, "open|filtered": 1 },
+        }), lines[1]);
+    }
+
+    #[test]
+    fn nmap_xml(){
+        let xml = write(Format::NmapXml, Protocol::Tcp, records());
+        assert!(xml.contains(r#"args="portscanner -p 1-100 &lt;&amp;&quot;x&quot;&gt;" start="1000""#), "{xml}");
+        assert!(xml.contains(r#"<scaninfo type="connect" protocol="tcp" numservices="100" services="1-100"/>"#));
+        assert!(xml.contains(r#"<host starttime="1001" endtime="1002">"#));
+        assert!(xml.contains(r#"<status state="up" reason="syn-ack"/>"#));
+        assert!(xml.contains(r#"<extraports state="closed" count="2"/>"#));
+        // ports come out in order, whatever order they were probed in
+        let ports: Vec<&str> = xml.lines().filter(|line| line.starts_with("<port ")).collect();
+        assert_eq!(vec![
+            r#"<port protocol="tcp" portid="22"><state state="open" reason="syn-ack" reason_ttl="0"/><service name="ssh" product="OpenSSH_9.6, &quot;p1&quot;" method="probed" conf="10"/></port>"#,
+            r#"<port protocol="tcp" portid="53"><state state="filtered" reason="no-response" reason_ttl="0"/></port>"#,
+            r#"<port protocol="tcp" portid="80"><state state="open" reason="syn-ack" reason_ttl="0"/></port>"#,
+        ], ports);
+        assert!(xml.contains(r#"<finished time="1010" elapsed="10.00" exit="success"/>"#));
+        assert!(xml.contains(r#"<hosts up="1" down="0" total="1"/>"#));
+        assert!(xml.ends_with("</nmaprun>\n"));
+    }
+
+    #[test]
+    fn escaping(){
+        assert_eq!("plain", csv_field("plain"));
+        assert_eq!("\"a,b\"", csv_field("a,b"));
+        assert_eq!("\"line\nbreak\"", csv_field("line\nbreak"));
+        assert_eq!("\"say \"\"hi\"\"\"", csv_field("say \"hi\""));
+        assert_eq!("a &lt;b&gt; &amp; &quot;c&quot; &apos;d&apos;", xml_escape("a <b> & \"c\" 'd'"));
+        assert_eq!("tab\tbell[31m\r\n", xml_escape("tab\tbell\x07\x1b[31m\0\r\n"));
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use crate::probe::Protocol;
+
+/// Service names accepted in place of a port number, with nmap's names
+/// alongside the ones people tend to type.
+const SERVICES: &[(&str, u16)] = &[
+    ("ftp", 21),
+    ("ssh", 22),
+    ("telnet", 23),
+    ("smtp", 25),
+    ("dns", 53),
+    ("domain", 53),
+    ("http", 80),
+    ("pop3", 110),
+    ("ntp", 123),
+    ("imap", 143),
+    ("snmp", 161),
+    ("ldap", 389),
+    ("https", 443),
+    ("smb", 445),
+    ("microsoft-ds", 445),
+    ("submission", 587),
+    ("imaps", 993),
+    ("pop3s", 995),
+    ("mssql", 1433),
+    ("ms-sql-s", 1433),
+    ("mysql", 3306),
+    ("rdp", 3389),
+    ("ms-wbt-server", 3389),
+    ("postgres", 5432),
+    ("postgresql", 5432),
+    ("vnc", 5900),
+    ("redis", 6379),
+    ("http-alt", 8080),
+    ("https-alt", 8443),
+];
+
+/// The most often open TCP ports, most frequent first, as ranked by nmap's
+/// `nmap-services`.
+const TOP_TCP: &[u16] = &[
+    80, 23, 443, 21, 22, 25, 3389, 110, 445, 139, 143, 53, 135, 3306, 8080, 1723, 111, 995, 993, 5900,
+    1025, 587, 8888, 199, 1720, 465, 548, 113, 81, 6001, 10000, 514, 5060, 179, 1026, 2000, 8443, 8000, 32768, 554,
+    26, 1433, 49152, 2001, 515, 8008, 49154, 1027, 5666, 646, 5000, 5631, 631, 49153, 8081, 2049, 88, 79, 5800, 106,
+    2121, 1110, 49155, 6000, 513, 990, 5357, 427, 49156, 543, 544, 5101, 144, 7, 389, 8009, 3128, 444, 9999, 5009,
+    7070, 5190, 3000, 5432, 1900, 3986, 13, 1029, 9, 5051, 6646, 49157, 1028, 873, 1755, 2717, 4899, 9100, 119, 37,
+];
+
+/// The same for UDP.
+const TOP_UDP: &[u16] = &[
+    631, 161, 137, 123, 138, 1434, 445, 135, 67, 53, 139, 500, 68, 520, 1900, 4500, 514, 49152, 162, 69,
+];
+
+/// A set of ports, each in it once, in ascending order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortSet(BTreeSet<u16>);
+
+impl PortSet {
+    pub fn range(start: u16, end: u16) -> PortSet {
+        PortSet((start..=end).collect())
+    }
+
+    /// The `n` ports most often found open for `protocol`, or an error if
+    /// we don't know of that many.
+    pub fn top(protocol: Protocol, n: usize) -> Result<PortSet, String> {
+        let table = match protocol {
+            Protocol::Tcp => TOP_TCP,
+            Protocol::Udp => TOP_UDP,
+        };
+        if n > table.len() {
+            return Err(format!("only the top {} {protocol} ports are known, not {n}", table.len()));
+        }
+        Ok(PortSet(table[..n].iter().copied().collect()))
+    }
+
+    /// Parses a comma-separated list of ports (`22`), ranges (`8000-8100`,
+    /// with either end left open as in `-1024` or `60000-`) and service names
+    /// (`ssh`).
+    pub fn parse(spec: &str) -> Result<PortSet, String> {
+        let mut ports = BTreeSet::new();
+        for item in spec.split(',').map(str::trim) {
+            if item.is_empty() {
+                return Err(format!("empty entry in port list `{spec}`"));
+            }
+            // names such as `http-alt` have dashes too, so they go first
+            if let Some((_, port)) = SERVICES.iter().find(|(name, _)| name.eq_ignore_ascii_case(item)) {
+                ports.insert(*port);
+            } else if !item.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+                return Err(format!("unknown service `{item}`"));
+            } else if let Some((start, end)) = item.split_once('-') {
+                let start = if start.is_empty() { 1 } else { port(start)? };
+                let end = if end.is_empty() { u16::MAX } else { port(end)? };
+                if start > end {
+                    return Err(format!("port range `{item}` ends before it starts"));
+                }
+                ports.extend(start..=end);
+            } else {
+                ports.insert(port(item)?);
+            }
+        }
+        Ok(PortSet(ports))
+    }
+
+    /// Takes out every port that is also in `other`.
+    pub fn remove(&mut self, other: &PortSet) {
+        self.0.retain(|port| !other.0.contains(port));
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+/// Writes the set the way nmap lists services, with runs of consecutive
+/// ports collapsed into ranges: `22,80,8000-8100`.
+impl fmt::Display for PortSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut ports = self.iter().peekable();
+        let mut first = true;
+        while let Some(start) = ports.next() {
+            let mut end = start;
+            while ports.next_if_eq(&end.wrapping_add(1)).is_some() {
+                end += 1;
+            }
+            if !first {
+                f.write_str(",")?;
+            }
+            first = false;
+            if start == end {
+                write!(f, "{start}")?;
+            } else {
+                write!(f, "{start}-{end}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn port(number: &str) -> Result<u16, String> {
+    match number.trim().parse::<u16>() {
+        Ok(0) | Err(_) => Err(format!("`{number}` is not a port between 1 and 65535")),
+        Ok(port) => Ok(port),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ports(spec: &str) -> Vec<u16> {
+        PortSet::parse(spec).unwrap().iter().collect()
+    }
+
+    #[test]
+    fn parse(){
+        assert_eq!(vec![22, 80, 443, 8000, 8001, 8002], ports("443, 80,8000-8002,ssh,HTTP"));
+        assert_eq!(vec![1, 2, 3], ports("-3"));
+        assert_eq!(vec![65534, 65535], ports("65534-"));
+        assert_eq!(65535, PortSet::parse("-").unwrap().len());
+        // names with dashes in them aren't ranges
+        assert_eq!(vec![445, 1433, 3389, 8080, 8443], ports("http-alt,https-alt,ms-sql-s,microsoft-ds,ms-wbt-server"));
+
+        for bad in ["", "22,", "0", "65536", "80-22", "22-x", "ssh-alt", "1-2-3"] {
+            assert!(PortSet::parse(bad).is_err(), "{bad}");
+        }
+        assert_eq!(Err("unknown service `gopher`".to_string()), PortSet::parse("gopher"));
+    }
+
+    #[test]
+    fn display_collapses_runs(){
+        assert_eq!("22,80,8000-8002,65535", PortSet::parse("8002,22,8000-8001,80,65535").unwrap().to_string());
+        assert_eq!("1-65535", PortSet::range(1, u16::MAX).to_string());
+        assert_eq!("", PortSet::default().to_string());
+    }
+
+    #[test]
+    fn top(){
+        assert_eq!(vec![23, 80, 443], PortSet::top(Protocol::Tcp, 3).unwrap().iter().collect::<Vec<_>>());
+        assert_eq!(TOP_TCP.len(), PortSet::top(Protocol::Tcp, TOP_TCP.len()).unwrap().len());
+        assert_eq!(TOP_UDP.len(), PortSet::top(Protocol::Udp, TOP_UDP.len()).unwrap().len());
+        assert!(PortSet::top(Protocol::Tcp, 1000).is_err());
+        assert!(PortSet::top(Protocol::Udp, TOP_UDP.len() + 1).is_err());
+    }
+
+    #[test]
+    fn remove(){
+        let mut ports = PortSet::range(20, 25);
+        ports.remove(&PortSet::parse("21,23-24,80").unwrap());
+        assert_eq!("20,22,25", ports.to_string());
+    }
+}
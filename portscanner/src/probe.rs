@@ -0,0 +1,167 @@
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::{self, Instant};
+use crate::limit::TokenBucket;
+
+/// Wait before the first retry; it doubles for every further one.
+pub const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// What a port looked like from here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortState {
+    /// The connection was accepted.
+    Open,
+    /// The host answered with a reset: nothing listens there.
+    Closed,
+    /// No answer, or an unreachable error, so something in between drops
+    /// the probes.
+    Filtered,
+    /// No answer to a UDP probe, which a listening service and a firewall
+    /// dropping it look the same.
+    OpenFiltered,
+}
+
+impl fmt::Display for PortState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PortState::Open => "open",
+            PortState::Closed => "closed",
+            PortState::Filtered => "filtered",
+            PortState::OpenFiltered => "open|filtered",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        })
+    }
+}
+
+/// How long each connection attempt may take and how often it is repeated.
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    pub timeout: Duration,
+    /// Further attempts after the first one times out.
+    pub retries: u32,
+}
+
+/// What probing a port found out.
+#[derive(Debug)]
+pub struct Answer {
+    pub state: PortState,
+    /// How long the attempt that settled `state` took; there is none for a
+    /// filtered port.
+    pub latency: Option<Duration>,
+    /// The connection, when the port is open.
+    pub stream: Option<TcpStream>,
+}
+
+/// Connects to `addr:port` to classify it. Only unanswered attempts are
+/// retried, since an accepted or refused connection is already conclusive.
+/// Errors that say nothing about the port, such as running out of file
+/// descriptors, are returned as is. Each retry waits for a token from
+/// `limiter`, if any; the caller takes the one for the first attempt.
+pub async fn probe(addr: IpAddr, port: u16, policy: Policy, limiter: Option<&TokenBucket>) -> io::Result<Answer> {
+    let mut backoff = INITIAL_BACKOFF;
+
+    for attempt in 0..=policy.retries {
+        if attempt > 0 {
+            time::sleep(backoff).await;
+            backoff *= 2;
+            if let Some(limiter) = limiter {
+                limiter.take().await;
+            }
+        }
+        let sent = Instant::now();
+        match time::timeout(policy.timeout, TcpStream::connect((addr, port))).await {
+            Ok(Ok(stream)) => {
+                return Ok(Answer { state: PortState::Open, latency: Some(sent.elapsed()), stream: Some(stream) });
+            }
+            Ok(Err(err)) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                return Ok(Answer { state: PortState::Closed, latency: Some(sent.elapsed()), stream: None });
+            }
+            Ok(Err(err)) if !matches!(
+                err.kind(),
+                io::ErrorKind::TimedOut | io::ErrorKind::HostUnreachable | io::ErrorKind::NetworkUnreachable
+            ) => return Err(err),
+            Ok(Err(_)) | Err(_) => {}
+        }
+    }
+
+    Ok(Answer { state: PortState::Filtered, latency: None, stream: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use tokio::net::{TcpListener, TcpSocket};
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn policy(timeout_ms: u64, retries: u32) -> Policy {
+        Policy { timeout: Duration::from_millis(timeout_ms), retries }
+    }
+
+    #[tokio::test]
+    async fn open_and_closed(){
+        let listener = TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let open = probe(LOCALHOST, port, policy(1000, 0), None).await.unwrap();
+        assert_eq!(PortState::Open, open.state);
+        assert!(open.latency.is_some() && open.stream.is_some());
+
+        // nothing listens on a port once its listener is gone
+        drop(listener);
+        let closed = probe(LOCALHOST, port, policy(1000, 0), None).await.unwrap();
+        assert_eq!(PortState::Closed, closed.state);
+        assert!(closed.latency.is_some() && closed.stream.is_none());
+    }
+
+    /// A port whose listener never accepts and has a full queue, so further
+    /// connection attempts go unanswered like behind a firewall.
+    async fn unanswered() -> (TcpListener, Vec<TcpStream>) {
+        let socket = TcpSocket::new_v4().unwrap();
+        socket.bind((LOCALHOST, 0).into()).unwrap();
+        let listener = socket.listen(0).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut queued = Vec::new();
+        while let Ok(Ok(stream)) = time::timeout(Duration::from_millis(100), TcpStream::connect(addr)).await {
+            queued.push(stream);
+        }
+        (listener, queued)
+    }
+
+    #[tokio::test]
+    async fn filtered_after_retries(){
+        let (listener, _queued) = unanswered().await;
+        let port = listener.local_addr().unwrap().port();
+
+        let start = Instant::now();
+        let filtered = probe(LOCALHOST, port, policy(100, 2), None).await.unwrap();
+        assert_eq!(PortState::Filtered, filtered.state);
+        assert!(filtered.latency.is_none() && filtered.stream.is_none());
+        // three timeouts, with 100ms and then 200ms of backoff in between
+        assert!(start.elapsed() >= Duration::from_millis(600), "{:?}", start.elapsed());
+
+        // retries wait for the limiter too; the first attempt's token is the caller's
+        let limiter = TokenBucket::new(1);
+        limiter.take().await;
+        let start = Instant::now();
+        probe(LOCALHOST, port, policy(100, 1), Some(&limiter)).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(900), "{:?}", start.elapsed());
+    }
+}
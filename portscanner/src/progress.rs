@@ -0,0 +1,92 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Clears the line the cursor is on.
+const CLEAR_LINE: &str = "\r\x1b[2K";
+
+/// Tracks how far a scan has come and draws it as a single, rewritten line
+/// on stderr. When stderr isn't a terminal nothing is drawn, so logs don't
+/// fill up with half-overwritten lines.
+#[derive(Debug)]
+pub struct Progress {
+    total: u64,
+    scanned: u64,
+    started: Instant,
+    visible: bool,
+}
+
+impl Progress {
+    pub fn new(total: u64, visible: bool) -> Progress {
+        Progress { total, scanned: 0, started: Instant::now(), visible }
+    }
+
+    pub fn advance(&mut self) {
+        self.scanned += 1;
+    }
+
+    /// Redraws the line with the current count and the estimated time left.
+    pub fn draw(&self) {
+        if !self.visible {
+            return;
+        }
+        let percent = self.scanned as f64 * 100.0 / self.total.max(1) as f64;
+        let mut err = io::stderr().lock();
+        let _ = write!(err, "{CLEAR_LINE}scanned {}/{} ({percent:.1}%), ETA {}", self.scanned, self.total, self.eta());
+        let _ = err.flush();
+    }
+
+    /// The time left, assuming the remaining ports go as fast as the ones
+    /// so far; `?` until there are any.
+    fn eta(&self) -> String {
+        match self.scanned {
+            0 => "?".to_string(),
+            scanned => {
+                let per_port = self.started.elapsed().as_secs_f64() / scanned as f64;
+                format_duration(Duration::from_secs_f64(per_port * self.total.saturating_sub(scanned) as f64))
+            }
+        }
+    }
+
+    /// Removes the line, so output written to the same terminal starts clean.
+    pub fn clear(&self) {
+        if self.visible {
+            let _ = write!(io::stderr().lock(), "{CLEAR_LINE}");
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h{:02}m{:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations(){
+        assert_eq!("0m00s", format_duration(Duration::ZERO));
+        assert_eq!("0m59s", format_duration(Duration::from_millis(59_900)));
+        assert_eq!("1m01s", format_duration(Duration::from_secs(61)));
+        assert_eq!("59m59s", format_duration(Duration::from_secs(3599)));
+        assert_eq!("1h00m00s", format_duration(Duration::from_secs(3600)));
+        assert_eq!("25h01m01s", format_duration(Duration::from_secs(90_061)));
+    }
+
+    #[test]
+    fn eta_from_the_pace_so_far(){
+        let mut progress = Progress::new(100, false);
+        assert_eq!("?", progress.eta());
+        // a quarter done in 10s leaves 30s
+        progress.started -= Duration::from_secs(10);
+        progress.scanned = 25;
+        assert_eq!("0m30s", progress.eta());
+        progress.scanned = 100;
+        assert_eq!("0m00s", progress.eta());
+    }
+}
@@ -0,0 +1,247 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use cidr::IpInet;
+use tokio::net;
+
+/// One entry of a target list, as given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Spec {
+    /// An address, a network or a range of addresses.
+    Range(Range),
+    /// A name still to be resolved.
+    Host(String),
+}
+
+impl Spec {
+    /// Parses an address (`10.0.0.1`), a network (`10.0.0.0/24`, host bits
+    /// allowed), a range (`10.0.0.1-10.0.0.50`, or `10.0.0.1-50` for the last
+    /// octet) or a hostname.
+    pub fn parse(text: &str) -> Result<Spec, String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err("empty target".to_string());
+        }
+        if text.contains('/') {
+            let inet: IpInet = text.parse().map_err(|err| format!("invalid network `{text}`: {err}"))?;
+            return Ok(Spec::Range(Range { start: inet.first_address(), end: inet.last_address() }));
+        }
+        if let Ok(addr) = text.parse::<IpAddr>() {
+            return Ok(Spec::Range(Range { start: addr, end: addr }));
+        }
+        // hostnames may contain dashes too, so only an address makes this a range
+        if let Some((start, end)) = text.split_once('-') {
+            if let Ok(start) = start.parse::<IpAddr>() {
+                let end = match (start, end.parse::<IpAddr>(), end.parse::<u8>()) {
+                    (_, Ok(end), _) if end.is_ipv4() == start.is_ipv4() => end,
+                    (IpAddr::V4(start), _, Ok(last)) => {
+                        let [a, b, c, _] = start.octets();
+                        Ipv4Addr::new(a, b, c, last).into()
+                    }
+                    _ => return Err(format!("invalid end of address range `{text}`")),
+                };
+                if start > end {
+                    return Err(format!("address range `{text}` ends before it starts"));
+                }
+                return Ok(Spec::Range(Range { start, end }));
+            }
+        }
+        Ok(Spec::Host(text.to_string()))
+    }
+}
+
+/// Addresses from `start` to `end`, both included, of the same family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    start: IpAddr,
+    end: IpAddr,
+}
+
+impl Range {
+    fn len(&self) -> u128 {
+        (to_bits(self.end) - to_bits(self.start)).saturating_add(1)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = IpAddr> {
+        let (start, ipv4) = (to_bits(self.start), self.start.is_ipv4());
+        (0..self.len()).map(move |offset| from_bits(start + offset, ipv4))
+    }
+}
+
+/// What to scan: every address of the included ranges that isn't in an
+/// excluded one, each only once.
+#[derive(Debug, Clone, Default)]
+pub struct Targets {
+    /// Disjoint, in ascending order, IPv4 first.
+    ranges: Vec<Range>,
+}
+
+impl Targets {
+    /// Resolves the hostnames among `include` and `exclude`, all at once.
+    /// A target that doesn't resolve is reported and left out, as the other
+    /// targets can still be scanned; an exclusion that doesn't is an error,
+    /// since whatever it was meant to keep out of the scan might be in it.
+    pub async fn resolve(include: Vec<Spec>, exclude: Vec<Spec>) -> Result<Targets, String> {
+        let (include, exclude) = tokio::join!(resolve_all(include), resolve_all(exclude));
+        let include = include.into_iter()
+            .filter_map(|range| range.inspect_err(|err| eprintln!("warning: failed to resolve {err}")).ok())
+            .collect();
+        let exclude = exclude.into_iter()
+            .collect::<Result<_, _>>()
+            .map_err(|err| format!("failed to resolve excluded {err}"))?;
+        Ok(Targets::new(include, exclude))
+    }
+
+    fn new(include: Vec<Range>, exclude: Vec<Range>) -> Targets {
+        let exclude = merge(exclude);
+        let mut ranges = Vec::new();
+        let mut excluded = exclude.iter().peekable();
+        for range in merge(include) {
+            // an exclusion that ends before this range ends before the later ones too
+            while excluded.next_if(|ex| ex.end < range.start).is_some() {}
+            let (ipv4, end) = (range.start.is_ipv4(), to_bits(range.end));
+            let mut start = Some(to_bits(range.start));
+            // only exclusions of the same family can overlap the range
+            for ex in excluded.clone().take_while(|ex| ex.start <= range.end) {
+                let Some(from) = start else { break };
+                let (ex_start, ex_end) = (to_bits(ex.start), to_bits(ex.end));
+                if ex_start > from {
+                    ranges.push(Range { start: from_bits(from, ipv4), end: from_bits(ex_start - 1, ipv4) });
+                }
+                start = (ex_end < end).then(|| ex_end + 1);
+            }
+            if let Some(from) = start {
+                ranges.push(Range { start: from_bits(from, ipv4), end: range.end });
+            }
+        }
+        Targets { ranges }
+    }
+
+    /// The addresses in ascending order, IPv4 first.
+    pub fn iter(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.ranges.iter().flat_map(Range::iter)
+    }
+
+    /// How many addresses `iter` yields.
+    pub fn count(&self) -> u64 {
+        let total = self.ranges.iter().fold(0u128, |total, range| total.saturating_add(range.len()));
+        u64::try_from(total).unwrap_or(u64::MAX)
+    }
+}
+
+/// Sorts `ranges` and joins the ones that overlap or touch.
+fn merge(mut ranges: Vec<Range>) -> Vec<Range> {
+    ranges.sort_unstable_by_key(|range| range.start);
+    let mut merged: Vec<Range> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if last.start.is_ipv4() == range.start.is_ipv4()
+                && to_bits(range.start) <= to_bits(last.end).saturating_add(1) => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+async fn resolve_all(specs: Vec<Spec>) -> Vec<Result<Range, String>> {
+    // every lookup is started before waiting for the first one
+    let lookups: Vec<_> = specs.into_iter()
+        .map(|spec| tokio::spawn(async move {
+            match spec {
+                Spec::Range(range) => Ok(range),
+                Spec::Host(name) => lookup(&name).await
+                    .map(|addr| Range { start: addr, end: addr })
+                    .map_err(|err| format!("{name}: {err}")),
+            }
+        }))
+        .collect();
+
+    let mut ranges = Vec::with_capacity(lookups.len());
+    for lookup in lookups {
+        ranges.push(lookup.await.unwrap());
+    }
+    ranges
+}
+
+/// The address `name` resolves to, preferring IPv4 as nmap does.
+async fn lookup(name: &str) -> io::Result<IpAddr> {
+    let addrs: Vec<IpAddr> = net::lookup_host((name, 0)).await?.map(|addr| addr.ip()).collect();
+    addrs.iter().find(|addr| addr.is_ipv4()).or(addrs.first()).copied()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses"))
+}
+
+fn to_bits(addr: IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(addr) => u32::from(addr).into(),
+        IpAddr::V6(addr) => u128::from(addr),
+    }
+}
+
+fn from_bits(bits: u128, ipv4: bool) -> IpAddr {
+    if ipv4 {
+        Ipv4Addr::from(bits as u32).into()
+    } else {
+        Ipv6Addr::from(bits).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(spec: &str) -> Range {
+        match Spec::parse(spec).unwrap() {
+            Spec::Range(range) => range,
+            Spec::Host(name) => panic!("{name} is not an address"),
+        }
+    }
+
+    fn targets(include: &[&str], exclude: &[&str]) -> Vec<String> {
+        let targets = Targets::new(include.iter().map(|s| range(s)).collect(), exclude.iter().map(|s| range(s)).collect());
+        let addrs: Vec<String> = targets.iter().map(|addr| addr.to_string()).collect();
+        assert_eq!(addrs.len() as u64, targets.count());
+        addrs
+    }
+
+    #[test]
+    fn parse(){
+        assert_eq!(range("10.0.0.0-10.0.0.255"), range("10.0.0.7/24"));
+        assert_eq!(range("10.0.0.1-10.0.0.50"), range("10.0.0.1-50"));
+        assert_eq!(range("::1-::1"), range("::1"));
+        assert_eq!(range("fe80::-fe80::ff"), range("fe80::/120"));
+        assert_eq!(Ok(Spec::Host("scanme.example-host.org".to_string())), Spec::parse(" scanme.example-host.org "));
+
+        for bad in ["", "10.0.0.0/33", "10.0.0.9-3", "10.0.0.1-::1", "10.0.0.1-300", "::1-2"] {
+            assert!(Spec::parse(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn merges_and_excludes(){
+        assert_eq!(vec!["10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.0.5", "::1"],
+            targets(&["::1", "10.0.0.3", "10.0.0.1-3", "10.0.0.5", "10.0.0.2", "::1"], &[]));
+        assert_eq!(vec!["10.0.0.1", "10.0.0.4", "10.0.0.8"],
+            targets(&["10.0.0.0/29", "10.0.0.8"], &["10.0.0.2-3", "10.0.0.5-7", "10.0.0.0", "::/0"]));
+        // one exclusion across several ranges, and another past the end
+        assert_eq!(vec!["10.0.0.1", "10.0.0.9"],
+            targets(&["10.0.0.1-2", "10.0.0.4-5", "10.0.0.7-9"], &["10.0.0.2-8", "10.0.1.0/24"]));
+        assert!(targets(&["10.0.0.0/24"], &["10.0.0.0/16"]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn unresolved_exclusions_are_errors(){
+        // `.invalid` never resolves
+        let host = || Spec::Host("nosuch.invalid".to_string());
+        let targets = Targets::resolve(vec![Spec::parse("10.0.0.1").unwrap(), host()], vec![]).await.unwrap();
+        assert_eq!(1, targets.count());
+        assert!(Targets::resolve(vec![Spec::parse("10.0.0.0/24").unwrap()], vec![host()]).await.is_err());
+    }
+
+    #[test]
+    fn counts_without_iterating(){
+        let everything = Targets::new(vec![range("0.0.0.0/0"), range("::/0")], vec![range("10.0.0.0/8")]);
+        assert_eq!(u64::MAX, everything.count());
+        let ipv4 = Targets::new(vec![range("0.0.0.0/0"), range("255.255.255.255")], vec![range("10.0.0.0/8")]);
+        assert_eq!((1 << 32) - (1 << 24), ipv4.count());
+        assert_eq!(Some(&range("11.0.0.0-255.255.255.255")), ipv4.ranges.last());
+    }
+}
@@ -0,0 +1,142 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use tokio::io::Interest;
+use tokio::net::UdpSocket;
+use tokio::time::{self, Instant};
+use crate::limit::TokenBucket;
+use crate::probe::{Answer, Policy, PortState, INITIAL_BACKOFF};
+
+/// Replies are rarely larger, and anything at all is enough to call a port open.
+const REPLY_LIMIT: usize = 2048;
+
+/// A DNS query for the root's name servers, recursion desired.
+const DNS_QUERY: &[u8] = &[
+    0x13, 0x37, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x02, 0x00, 0x01,
+];
+
+/// An NTPv3 client request: mode 3, everything else zero.
+const NTP_REQUEST: &[u8] = &[
+    0x1b, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// An SNMPv1 get-request for sysDescr.0 with the `public` community.
+const SNMP_GET: &[u8] = &[
+    0x30, 0x29, 0x02, 0x01, 0x00, 0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c',
+    0xa0, 0x1c, 0x02, 0x04, 0x00, 0x00, 0x00, 0x01, 0x02, 0x01, 0x00, 0x02, 0x01, 0x00,
+    0x30, 0x0e, 0x30, 0x0c, 0x06, 0x08, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00, 0x05, 0x00,
+];
+
+/// What to send to `port`. Most UDP services ignore a datagram they can't
+/// parse, so well-known ports get a request in their own protocol; the rest
+/// get an empty datagram, which at least draws an ICMP error when closed.
+fn payload(port: u16) -> &'static [u8] {
+    match port {
+        53 => DNS_QUERY,
+        123 => NTP_REQUEST,
+        161 => SNMP_GET,
+        _ => &[],
+    }
+}
+
+/// Sends a datagram to `addr:port` and classifies the port by what comes
+/// back: any reply means open, an ICMP port unreachable (surfacing as a
+/// refused connection) means closed. Silence is ambiguous, since either the
+/// service or a firewall may drop the datagram, so such ports are
+/// `OpenFiltered` once every retry went unanswered. Retries are paced by
+/// `limiter` like in `probe::probe`.
+pub async fn probe(addr: IpAddr, port: u16, policy: Policy, limiter: Option<&TokenBucket>) -> io::Result<Answer> {
+    let local: IpAddr = if addr.is_ipv4() { Ipv4Addr::UNSPECIFIED.into() } else { Ipv6Addr::UNSPECIFIED.into() };
+    let socket = UdpSocket::bind((local, 0)).await?;
+    // a connected socket is what gets ICMP errors reported back to it
+    socket.connect((addr, port)).await?;
+
+    let mut backoff = INITIAL_BACKOFF;
+    let mut reply = [0; REPLY_LIMIT];
+    for attempt in 0..=policy.retries {
+        if attempt > 0 {
+            time::sleep(backoff).await;
+            backoff *= 2;
+            if let Some(limiter) = limiter {
+                limiter.take().await;
+            }
+        }
+        let sent = Instant::now();
+        socket.send(payload(port)).await?;
+        match time::timeout(policy.timeout, recv(&socket, &mut reply)).await {
+            Ok(Ok(_)) => return Ok(Answer { state: PortState::Open, latency: Some(sent.elapsed()), stream: None }),
+            Ok(Err(err)) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                return Ok(Answer { state: PortState::Closed, latency: Some(sent.elapsed()), stream: None });
+            }
+            // other ICMP unreachables come from a router or firewall, not the port
+            Ok(Err(err)) if matches!(err.kind(), io::ErrorKind::HostUnreachable | io::ErrorKind::NetworkUnreachable) => {
+                return Ok(Answer { state: PortState::Filtered, latency: None, stream: None });
+            }
+            Ok(Err(err)) => return Err(err),
+            Err(_) => {}
+        }
+    }
+
+    Ok(Answer { state: PortState::OpenFiltered, latency: None, stream: None })
+}
+
+/// Like `UdpSocket::recv`, but also wakes up for a pending ICMP error, which
+/// only marks the socket as errored rather than readable.
+async fn recv(socket: &UdpSocket, buf: &mut [u8]) -> io::Result<usize> {
+    socket.async_io(Interest::READABLE | Interest::ERROR, || match socket.take_error()? {
+        Some(err) => Err(err),
+        None => socket.try_recv(buf),
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn policy(timeout_ms: u64) -> Policy {
+        Policy { timeout: Duration::from_millis(timeout_ms), retries: 0 }
+    }
+
+    #[test]
+    fn well_known_payloads(){
+        assert_eq!(DNS_QUERY, payload(53));
+        assert_eq!(NTP_REQUEST, payload(123));
+        assert_eq!(SNMP_GET, payload(161));
+        assert!(payload(9999).is_empty());
+    }
+
+    #[tokio::test]
+    async fn open_when_answered(){
+        let server = UdpSocket::bind((LOCALHOST, 0)).await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        let echo = tokio::spawn(async move {
+            let mut buf = [0; 64];
+            let (n, from) = server.recv_from(&mut buf).await.unwrap();
+            server.send_to(&buf[..n], from).await.unwrap();
+        });
+        let open = probe(LOCALHOST, port, policy(1000), None).await.unwrap();
+        assert_eq!(PortState::Open, open.state);
+        assert!(open.latency.is_some());
+        echo.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn closed_or_silent(){
+        let server = UdpSocket::bind((LOCALHOST, 0)).await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        // a bound socket that never answers could as well be a firewall
+        let silent = probe(LOCALHOST, port, policy(100), None).await.unwrap();
+        assert_eq!(PortState::OpenFiltered, silent.state);
+        assert!(silent.latency.is_none());
+
+        // once nothing is bound, the port unreachable comes back
+        drop(server);
+        let closed = probe(LOCALHOST, port, policy(1000), None).await.unwrap();
+        assert_eq!(PortState::Closed, closed.state);
+    }
+}